@@ -1,6 +1,5 @@
 #import bevy_pbr::mesh_functions::{get_world_from_local, mesh_position_local_to_clip}
 
-#import blocks::texture_layer;
 #import utils::rgb_to_gray;
 
 @group(2) @binding(0) var overlay_tex: texture_2d<f32>;
@@ -8,6 +7,7 @@
 @group(2) @binding(2) var blocks_tex: texture_2d_array<f32>;
 @group(2) @binding(3) var blocks_smp: sampler;
 @group(2) @binding(4) var<uniform> block_id: u32;
+@group(2) @binding(5) var<storage, read> texture_layers: array<u32>;
 
 struct Vertex {
     @builtin(vertex_index) vertex_index: u32,
@@ -35,7 +35,7 @@ fn vertex(vertex: Vertex) -> VertexOutput {
     );
     out.clip_position.z += 0.0001;
     out.uv = vertex.uv;
-    out.layer = texture_layer(block_id, direction);
+    out.layer = texture_layers[block_id * 6u + direction];
 
     return out;
 }
@@ -1,18 +1,20 @@
 #import bevy_pbr::mesh_functions::{get_world_from_local, mesh_position_local_to_clip}
 
-#import blocks::{texture_layer};
-
 const chunk_width = 16i;
-const chunk_height = 256i;
+const section_height = 16i;
+const max_quad_size = 16i;
+const max_light = 15u;
+const fluid_levels = 9.0;
 
 @group(2) @binding(0) var tex: texture_2d_array<f32>;
 @group(2) @binding(1) var smp: sampler;
-@group(2) @binding(2) var<uniform> offset: vec2i;
+@group(2) @binding(2) var<storage, read> texture_layers: array<u32>;
 
 struct Vertex {
     @builtin(instance_index) instance_index: u32,
     @builtin(vertex_index) vertex_index: u32,
     @location(0) data: i32,
+    @location(1) light: u32,
 };
 
 struct VertexOutput {
@@ -35,35 +37,59 @@ fn vertex(vertex: Vertex) -> VertexOutput {
     );
     var uvs = array<vec2f, 4>(vec2f(0, 1), vec2f(1, 1), vec2f(1, 0), vec2f(0, 0));
     var brightness_levels = array<f32, 6>(0.8, 0.6, 0.8, 0.6, 1.0, 0.5);
+    var ao_levels = array<f32, 4>(0.4, 0.6, 0.8, 1.0);
 
     var out: VertexOutput;
 
     let x = vertex.data & (chunk_width - 1);
     let z = (vertex.data >> u32(log2(f32(chunk_width)))) & (chunk_width - 1);
-    let y = (vertex.data >> u32(log2(f32(chunk_width)) * 2)) & (chunk_height - 1);
-    let direction = u32((vertex.data >> u32(log2(f32(chunk_width)) * 2 + log2(f32(chunk_height)))) & 7);
-    let block_id = u32((vertex.data >> u32(log2(f32(chunk_width)) * 2 + log2(f32(chunk_height))) + 3) & 3);
+    let y = (vertex.data >> u32(log2(f32(chunk_width)) * 2)) & (section_height - 1);
+    let direction = u32((vertex.data >> u32(log2(f32(chunk_width)) * 2 + log2(f32(section_height)))) & 7);
+    let block_id = u32((vertex.data >> u32(log2(f32(chunk_width)) * 2 + log2(f32(section_height))) + 3) & 255);
+    let quad_width = ((vertex.data >> u32(log2(f32(chunk_width)) * 2 + log2(f32(section_height))) + 11) & (max_quad_size - 1)) + 1;
+    let quad_height = ((vertex.data >> u32(log2(f32(chunk_width)) * 2 + log2(f32(section_height)) + log2(f32(max_quad_size))) + 11) & (max_quad_size - 1)) + 1;
+
+    var quad_sizes = array<vec3i, 6>(
+        vec3i(quad_width, quad_height, 1), // north (+z)
+        vec3i(quad_width, quad_height, 1), // south (-z)
+        vec3i(1, quad_height, quad_width), // west  (+x)
+        vec3i(1, quad_height, quad_width), // east  (-x)
+        vec3i(quad_width, 1, quad_height), // up    (+y)
+        vec3i(quad_width, 1, quad_height), // down  (-y)
+    );
 
     let vertex_idx = vertex.vertex_index & 3;
-    let vertex_pos = block_vertices[direction][vertex_idx];
+    let vertex_pos = block_vertices[direction][vertex_idx] * quad_sizes[direction];
+    // The top edge of the face is lowered by the depth of the fluid surface.
+    let depth = f32((vertex.light >> 10) & 7) / fluid_levels;
+    let lowered = f32(block_vertices[direction][vertex_idx].y) * depth;
 
     out.clip_position = mesh_position_local_to_clip(
         get_world_from_local(vertex.instance_index),
+        // Section entities are positioned at the center of the section.
         vec4f(
-            f32(x + vertex_pos.x + offset.x * chunk_width),
-            f32(y + vertex_pos.y),
-            f32(z + vertex_pos.z + offset.y * chunk_width),
+            f32(x + vertex_pos.x) - f32(chunk_width) / 2.0,
+            f32(y + vertex_pos.y) - lowered - f32(section_height) / 2.0,
+            f32(z + vertex_pos.z) - f32(chunk_width) / 2.0,
             1.0
         ),
     );
-    out.uv = uvs[vertex_idx];
-    out.layer = texture_layer(block_id, direction);
-    out.brightness = brightness_levels[direction];
+    out.uv = uvs[vertex_idx] * vec2f(f32(quad_width), f32(quad_height));
+    out.layer = texture_layers[block_id * 6u + direction];
+    let light = max((vertex.light >> 4) & max_light, vertex.light & max_light);
+    let ao = (vertex.light >> 8) & 3;
+    out.brightness = brightness_levels[direction] * pow(0.8, f32(max_light - light)) * ao_levels[ao];
 
     return out;
 }
 
 @fragment
 fn fragment(in: VertexOutput) -> @location(0) vec4f {
-    return textureSample(tex, smp, in.uv, in.layer) * in.brightness;
+    let color = textureSample(tex, smp, in.uv, in.layer);
+#ifndef TRANSLUCENT
+    if color.a < 0.5 {
+        discard;
+    }
+#endif
+    return vec4f(color.rgb * in.brightness, color.a);
 }
@@ -0,0 +1,3 @@
+create table chunks
+(x integer not null, z integer not null, blocks blob not null, primary key (x, z))
+strict, without rowid;
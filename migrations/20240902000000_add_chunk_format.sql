@@ -0,0 +1 @@
+alter table chunks add column format integer not null default 0;
@@ -0,0 +1,6 @@
+create table world
+(id integer primary key check (id = 0), seed integer not null)
+strict;
+
+-- Worlds created before seeds were stored were all generated with seed 0.
+insert into world (id, seed) select 0, 0 where exists (select 1 from chunks);
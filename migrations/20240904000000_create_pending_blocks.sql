@@ -0,0 +1,5 @@
+create table pending_blocks
+(x integer not null, z integer not null, blocks blob not null)
+strict;
+
+create index pending_blocks_offset on pending_blocks (x, z);
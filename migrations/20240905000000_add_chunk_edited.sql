@@ -0,0 +1,2 @@
+-- Whether the player edited the chunk, chunks that weren't edited can be generated again.
+alter table chunks add column edited integer not null default 0;
@@ -0,0 +1,4 @@
+-- Block the player spawns on top of, null until it is found.
+alter table world add column spawn_x integer;
+alter table world add column spawn_y integer;
+alter table world add column spawn_z integer;
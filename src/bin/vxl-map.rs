@@ -0,0 +1,3 @@
+fn main() -> anyhow::Result<()> {
+    vxl::map()
+}
@@ -1,57 +1,575 @@
-use strum::EnumCount;
+use std::{fmt, ops::Index, sync::Arc};
 
-#[derive(EnumCount, Clone, Copy, PartialEq, Eq, Debug)]
-#[repr(u8)]
-pub(super) enum BlockId {
-    Air,
-    Grass,
-    Dirt,
-    Stone,
-}
+use bevy::{asset::LoadState, prelude::*};
+use serde::Deserialize;
+use strum::IntoEnumIterator;
+
+use crate::{direction::Direction, sets::LoadingSet, state::AppState, toml_asset::TomlAsset};
 
-#[derive(PartialEq, Eq, Debug)]
+/// Index into the [`BlockRegistry`]. Ids are given in `blocks.toml` and stored in saves, so they
+/// must not change once used. 0 is the built-in air block.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub(super) struct BlockId(u8);
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
 enum Transparency {
+    /// Not rendered at all.
     Transparent,
     Opaque,
+    /// Rendered with the solid geometry, with fully transparent texels discarded.
+    Cutout,
+    /// Alpha blended. Faces between two blocks of the same kind are not rendered.
+    Translucent,
+}
+
+#[derive(Debug)]
+pub(super) struct Block {
+    name: String,
+    solid: bool,
+    fluid: Option<Fluid>,
+    transparency: Transparency,
+    light: u8,
+    textures: [u32; 6],
+}
+
+/// Every fluid defined in `blocks.toml` is a source block, followed in the registry by flowing
+/// blocks of every lower level.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(super) struct Fluid {
+    source: BlockId,
+    level: u8,
+}
+
+#[derive(Resource, Clone, Debug)]
+pub(super) struct BlockRegistry(Arc<Vec<Block>>);
+
+/// Problem with `blocks.toml`.
+#[derive(Debug)]
+pub(super) enum BlocksError {
+    /// The file isn't valid TOML.
+    Load(String),
+    /// A value has the wrong type, or a key is unknown.
+    Invalid(toml::de::Error),
+    DuplicateName(String),
+    /// The id, or one of the ids of the flowing blocks of a fluid, is air or already used.
+    DuplicateId {
+        name: String,
+        id: u8,
+    },
+    /// The flowing blocks of a fluid would need ids above 255.
+    IdOutOfRange(String),
+    Light {
+        name: String,
+        light: u8,
+    },
+    MissingTexture {
+        name: String,
+        dir: Direction,
+    },
+}
+
+/// Every problem found in the last loaded version of `blocks.toml`.
+#[derive(Resource, Debug)]
+pub(super) struct BlocksErrors(Vec<BlocksError>);
+
+#[derive(Component, Debug)]
+struct BlocksErrorsUi;
+
+#[derive(Resource, Debug)]
+pub(super) struct LoadingBlocks {
+    handle: Handle<TomlAsset>,
+    is_loaded: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct BlocksConfig {
+    blocks: Vec<BlockConfig>,
 }
 
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct BlockConfig {
+    id: u8,
+    name: String,
+    solid: bool,
+    /// Fluids can be swum in, and flow into air around them.
+    #[serde(default)]
+    fluid: bool,
+    transparency: Transparency,
+    #[serde(default)]
+    light: u8,
+    textures: TexturesConfig,
+}
+
+/// Texture tiles of a block. More specific faces take precedence over `side`, `top` and `bottom`,
+/// which in turn take precedence over `all`.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct TexturesConfig {
+    all: Option<u32>,
+    side: Option<u32>,
+    top: Option<u32>,
+    bottom: Option<u32>,
+    north: Option<u32>,
+    south: Option<u32>,
+    west: Option<u32>,
+    east: Option<u32>,
+}
+
+#[derive(Debug)]
+pub(super) struct BlocksPlugin;
+
 impl BlockId {
-    fn transparency(self) -> Transparency {
-        match self {
-            BlockId::Air => Transparency::Transparent,
-            BlockId::Grass => Transparency::Opaque,
-            BlockId::Dirt => Transparency::Opaque,
-            BlockId::Stone => Transparency::Opaque,
+    pub(super) const AIR: Self = Self(0);
+}
+
+impl From<u8> for BlockId {
+    fn from(value: u8) -> Self {
+        Self(value)
+    }
+}
+
+impl From<BlockId> for u8 {
+    fn from(id: BlockId) -> Self {
+        id.0
+    }
+}
+
+impl Block {
+    fn air() -> Self {
+        Self {
+            name: "air".into(),
+            solid: false,
+            fluid: None,
+            transparency: Transparency::Transparent,
+            light: 0,
+            textures: [u32::MAX; 6],
+        }
+    }
+
+    pub(super) fn is_transparent(&self) -> bool {
+        self.transparency == Transparency::Transparent
+    }
+
+    pub(super) fn is_opaque(&self) -> bool {
+        self.transparency == Transparency::Opaque
+    }
+
+    pub(super) fn is_translucent(&self) -> bool {
+        self.transparency == Transparency::Translucent
+    }
+
+    pub(super) fn is_solid(&self) -> bool {
+        self.solid
+    }
+
+    pub(super) fn is_fluid(&self) -> bool {
+        self.fluid.is_some()
+    }
+
+    pub(super) fn fluid(&self) -> Option<Fluid> {
+        self.fluid
+    }
+
+    /// Level of the light emitted by the block.
+    pub(super) fn light(&self) -> u8 {
+        self.light
+    }
+}
+
+impl Fluid {
+    pub(super) const MAX_LEVEL: u8 = 8;
+
+    pub(super) fn level(self) -> u8 {
+        self.level
+    }
+
+    pub(super) fn is_source(self) -> bool {
+        self.level == Self::MAX_LEVEL
+    }
+
+    /// Returns whether both blocks are the same fluid, regardless of their levels.
+    pub(super) fn is_same(self, other: Option<Fluid>) -> bool {
+        other.is_some_and(|other| other.source == self.source)
+    }
+
+    /// Block of the same fluid with the given level, or air if it is 0.
+    pub(super) fn with_level(self, level: u8) -> BlockId {
+        debug_assert!(level <= Self::MAX_LEVEL);
+        match level {
+            0 => BlockId::AIR,
+            _ => BlockId(self.source.0 + Self::MAX_LEVEL - level),
+        }
+    }
+}
+
+impl BlockRegistry {
+    /// Parses the registry, reporting every problem with it that can be found.
+    pub(super) fn from_toml(table: &TomlAsset) -> Result<Self, BlocksErrors> {
+        let config = BlocksConfig::deserialize(toml::Value::Table(table.0.clone()))
+            .map_err(|err| BlocksErrors(vec![BlocksError::Invalid(err)]))?;
+
+        let mut errors = Vec::new();
+        let mut blocks: Vec<Option<Block>> = vec![Some(Block::air())];
+        let mut names = vec![String::from("air")];
+        for block in config.blocks {
+            if block.light > BlocksPlugin::MAX_LIGHT {
+                errors.push(BlocksError::Light {
+                    name: block.name.clone(),
+                    light: block.light,
+                });
+            }
+
+            let mut textures = [0; 6];
+            for dir in Direction::iter() {
+                match block.textures.get(dir) {
+                    Some(texture) => textures[dir as usize] = texture,
+                    None => errors.push(BlocksError::MissingTexture {
+                        name: block.name.clone(),
+                        dir,
+                    }),
+                }
+            }
+
+            let levels = if block.fluid { Fluid::MAX_LEVEL } else { 1 };
+            let Some(last) = block.id.checked_add(levels - 1) else {
+                errors.push(BlocksError::IdOutOfRange(block.name));
+                continue;
+            };
+            if blocks.len() <= last as usize {
+                blocks.resize_with(last as usize + 1, || None);
+            }
+
+            // Flowing blocks of a fluid follow its source block, from the highest level down.
+            let source = BlockId(block.id);
+            for (id, level) in (block.id..=last).zip((1..=levels).rev()) {
+                let name = if level == levels {
+                    block.name.clone()
+                } else {
+                    format!("flowing_{}_{level}", block.name)
+                };
+                if names.contains(&name) {
+                    errors.push(BlocksError::DuplicateName(name.clone()));
+                }
+                names.push(name.clone());
+
+                if blocks[id as usize].is_some() {
+                    errors.push(BlocksError::DuplicateId {
+                        name: block.name.clone(),
+                        id,
+                    });
+                    continue;
+                }
+
+                blocks[id as usize] = Some(Block {
+                    name,
+                    solid: block.solid,
+                    fluid: block.fluid.then_some(Fluid { source, level }),
+                    transparency: block.transparency,
+                    light: block.light,
+                    textures,
+                });
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(BlocksErrors(errors));
         }
+
+        // Unused ids are air, like ids of blocks removed after a world was saved.
+        let blocks = blocks
+            .into_iter()
+            .map(|block| block.unwrap_or_else(Block::air))
+            .collect();
+        Ok(Self(Arc::new(blocks)))
     }
 
-    pub(super) fn is_transparent(self) -> bool {
-        self.transparency() == Transparency::Transparent
+    pub(super) fn id(&self, name: &str) -> Option<BlockId> {
+        self.0
+            .iter()
+            .position(|block| block.name == name)
+            .map(|id| BlockId(id as u8))
     }
 
-    pub(super) fn is_opaque(self) -> bool {
-        self.transparency() == Transparency::Opaque
+    /// Texture layer of every face of every block, indexed by `block id * 6 + direction`.
+    pub(super) fn texture_layers(&self) -> Vec<u32> {
+        self.0
+            .iter()
+            .flat_map(|block| block.textures.iter().copied())
+            .collect()
     }
+}
+
+#[cfg(test)]
+impl BlockRegistry {
+    /// Registry of the blocks in `assets/blocks.toml`.
+    pub(super) fn from_assets() -> Self {
+        Self::from_toml(&TomlAsset::read(std::path::Path::new("assets/blocks.toml")).unwrap())
+            .unwrap()
+    }
+}
 
-    pub(super) fn is_solid(self) -> bool {
+/// Ids not present in the registry, e.g. of blocks removed from `blocks.toml` after a world was
+/// saved, resolve to air.
+impl Index<BlockId> for BlockRegistry {
+    type Output = Block;
+    fn index(&self, id: BlockId) -> &Self::Output {
+        self.0.get(id.0 as usize).unwrap_or(&self.0[0])
+    }
+}
+
+impl fmt::Display for BlocksError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            BlockId::Air => false,
-            BlockId::Grass => true,
-            BlockId::Dirt => true,
-            BlockId::Stone => true,
+            Self::Load(err) => write!(f, "failed to load: {err}"),
+            Self::Invalid(err) => write!(f, "{}", err.message()),
+            Self::DuplicateName(name) => write!(f, "block `{name}` is defined more than once"),
+            Self::DuplicateId { name, id } => write!(f, "id {id} of `{name}` is already used"),
+            Self::IdOutOfRange(name) => write!(
+                f,
+                "flowing blocks of `{name}` need the {} ids after it, at most up to 255",
+                Fluid::MAX_LEVEL - 1
+            ),
+            Self::Light { name, light } => write!(
+                f,
+                "light level of `{name}` must be at most {}, got {light}",
+                BlocksPlugin::MAX_LIGHT
+            ),
+            Self::MissingTexture { name, dir } => {
+                write!(f, "missing {dir:?} texture for `{name}`")
+            }
         }
     }
 }
 
-impl From<u8> for BlockId {
-    fn from(value: u8) -> Self {
-        debug_assert!(value < BlockId::COUNT as u8);
-        match value {
-            0 => BlockId::Air,
-            1 => BlockId::Grass,
-            2 => BlockId::Dirt,
-            3 => BlockId::Stone,
-            _ => unreachable!(),
+impl fmt::Display for BlocksErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for err in &self.0 {
+            writeln!(f, "{err}")?;
+        }
+        Ok(())
+    }
+}
+
+impl TexturesConfig {
+    fn get(&self, dir: Direction) -> Option<u32> {
+        let texture = match dir {
+            Direction::North => self.north.or(self.side),
+            Direction::South => self.south.or(self.side),
+            Direction::West => self.west.or(self.side),
+            Direction::East => self.east.or(self.side),
+            Direction::Up => self.top,
+            Direction::Down => self.bottom,
+        };
+        texture.or(self.all)
+    }
+}
+
+impl FromWorld for LoadingBlocks {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world.resource::<AssetServer>();
+
+        LoadingBlocks {
+            handle: asset_server.load("blocks.toml"),
+            is_loaded: false,
+        }
+    }
+}
+
+impl Plugin for BlocksPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LoadingBlocks>()
+            .add_systems(
+                Update,
+                (
+                    Self::create_block_registry,
+                    Self::display_block_errors
+                        .run_if(resource_changed_or_removed::<BlocksErrors>()),
+                )
+                    .chain()
+                    .in_set(LoadingSet),
+            )
+            .add_systems(OnExit(AppState::Loading), Self::despawn_block_errors);
+    }
+}
+
+impl BlocksPlugin {
+    const MAX_LIGHT: u8 = 15;
+
+    pub(super) fn is_loaded(registry: Option<Res<BlockRegistry>>) -> bool {
+        registry.is_some()
+    }
+
+    /// Creates the registry once `blocks.toml` is loaded, and again whenever it changes while
+    /// loading, so that an invalid file can be fixed without restarting.
+    fn create_block_registry(
+        mut commands: Commands,
+        mut events: EventReader<AssetEvent<TomlAsset>>,
+        asset_server: Res<AssetServer>,
+        mut loading_blocks: ResMut<LoadingBlocks>,
+        toml_assets: Res<Assets<TomlAsset>>,
+    ) {
+        if events.read().any(|ev| {
+            ev.is_loaded_with_dependencies(&loading_blocks.handle)
+                || ev.is_modified(&loading_blocks.handle)
+        }) {
+            loading_blocks.is_loaded = false;
+        }
+        if loading_blocks.is_loaded {
+            return;
+        }
+
+        let registry = match asset_server.load_state(&loading_blocks.handle) {
+            LoadState::Loaded => {
+                BlockRegistry::from_toml(toml_assets.get(&loading_blocks.handle).unwrap())
+            }
+            LoadState::Failed(err) => Err(BlocksErrors(vec![BlocksError::Load(err.to_string())])),
+            _ => return,
+        };
+        loading_blocks.is_loaded = true;
+
+        match registry {
+            Ok(registry) => {
+                commands.insert_resource(registry);
+                commands.remove_resource::<BlocksErrors>();
+            }
+            Err(errors) => {
+                error!("invalid `blocks.toml`:\n{errors}");
+                commands.insert_resource(errors);
+            }
+        }
+    }
+
+    fn display_block_errors(
+        mut commands: Commands,
+        errors: Option<Res<BlocksErrors>>,
+        query: Query<Entity, With<BlocksErrorsUi>>,
+    ) {
+        for entity in &query {
+            commands.entity(entity).despawn_recursive();
+        }
+        let Some(errors) = errors else {
+            return;
+        };
+
+        let text_style = TextStyle {
+            font_size: 24.0,
+            ..Default::default()
+        };
+
+        commands.spawn((
+            TextBundle::from_sections([
+                TextSection::new("Invalid `blocks.toml`:\n", text_style.clone()),
+                TextSection::new(errors.to_string(), text_style),
+            ])
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(5.0),
+                left: Val::Px(5.0),
+                ..Default::default()
+            }),
+            BlocksErrorsUi,
+        ));
+    }
+
+    fn despawn_block_errors(mut commands: Commands, query: Query<Entity, With<BlocksErrorsUi>>) {
+        for entity in &query {
+            commands.entity(entity).despawn_recursive();
         }
+        commands.remove_resource::<BlocksErrors>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_str(toml: &str) -> Result<BlockRegistry, BlocksErrors> {
+        BlockRegistry::from_toml(&TomlAsset(toml.parse().unwrap()))
+    }
+
+    #[test]
+    fn ids_are_stable() {
+        let blocks = BlockRegistry::from_assets();
+        assert_eq!(blocks.id("stone"), Some(BlockId(3)));
+        assert_eq!(blocks.id("water"), Some(BlockId(10)));
+        assert_eq!(blocks.id("flowing_water_1"), Some(BlockId(17)));
+
+        let water = blocks[BlockId(10)].fluid().unwrap();
+        assert!(water.is_source());
+        assert_eq!(water.with_level(1), BlockId(17));
+    }
+
+    #[test]
+    fn unused_ids_are_air() {
+        let blocks = from_str(
+            r#"
+            [[blocks]]
+            id = 5
+            name = "stone"
+            solid = true
+            transparency = "opaque"
+            textures = { all = 0 }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(blocks.id("stone"), Some(BlockId(5)));
+        assert!(!blocks[BlockId(2)].is_solid());
+        assert!(blocks[BlockId(2)].is_transparent());
+    }
+
+    #[test]
+    fn reports_every_error() {
+        let errors = from_str(
+            r#"
+            [[blocks]]
+            id = 0
+            name = "stone"
+            solid = true
+            transparency = "opaque"
+            light = 16
+            textures = { all = 0 }
+
+            [[blocks]]
+            id = 1
+            name = "water"
+            solid = false
+            fluid = true
+            transparency = "translucent"
+            textures = { all = 1 }
+
+            [[blocks]]
+            id = 3
+            name = "stone"
+            solid = true
+            transparency = "opaque"
+            textures = { side = 0 }
+
+            [[blocks]]
+            id = 250
+            name = "lava"
+            solid = false
+            fluid = true
+            transparency = "opaque"
+            textures = { all = 2 }
+            "#,
+        )
+        .unwrap_err();
+
+        let errors: Vec<_> = errors.0.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors,
+            [
+                "light level of `stone` must be at most 15, got 16",
+                "id 0 of `stone` is already used",
+                "missing Up texture for `stone`",
+                "missing Down texture for `stone`",
+                "block `stone` is defined more than once",
+                "id 3 of `stone` is already used",
+                "flowing blocks of `lava` need the 7 ids after it, at most up to 255",
+            ]
+        );
     }
 }
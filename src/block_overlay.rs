@@ -1,7 +1,9 @@
 use bevy::prelude::*;
 
 use crate::{
+    block::BlockRegistry,
     materials::BlockOverlayMaterial,
+    player::PlayerPlugin,
     sets::GameplaySet,
     state::AppState,
     textures::{BlockOverlayTexture, BlocksTexture},
@@ -30,12 +32,11 @@ impl Plugin for BlockOverlayPlugin {
 }
 
 impl BlockOverlayPlugin {
-    const REACH: f32 = 4.5;
-
     fn spawn_overlay(
         mut commands: Commands,
         overlay: Res<BlockOverlayTexture>,
-        blocks: Res<BlocksTexture>,
+        texture: Res<BlocksTexture>,
+        blocks: Res<BlockRegistry>,
         mut meshes: ResMut<Assets<Mesh>>,
         mut materials: ResMut<Assets<BlockOverlayMaterial>>,
     ) {
@@ -45,11 +46,12 @@ impl BlockOverlayPlugin {
         commands.spawn((
             MaterialMeshBundle {
                 mesh: meshes.add(mesh),
-                material: materials.add(BlockOverlayMaterial::new(&overlay.0, &blocks.0)),
+                material: materials.add(BlockOverlayMaterial::new(&overlay.0, &texture.0, &blocks)),
                 visibility: Visibility::Hidden,
                 ..Default::default()
             },
             BlockOverlay,
+            StateScoped(AppState::InGame),
         ));
     }
 
@@ -67,6 +69,7 @@ impl BlockOverlayPlugin {
         )>,
 
         chunks: Res<Chunks>,
+        blocks: Res<BlockRegistry>,
         mut materials: ResMut<Assets<BlockOverlayMaterial>>,
     ) {
         let q_camera = set.p0();
@@ -77,12 +80,16 @@ impl BlockOverlayPlugin {
         let mut q_overlay = set.p1();
         let (mut transform, mut visibility, handle) = q_overlay.single_mut();
 
-        match chunks.traverse(Ray3d::new(translation, direction), Self::REACH) {
-            Some((pos, block_id)) => {
-                *transform = Transform::from_translation(pos.as_vec3() + Vec3::splat(0.5));
+        match chunks.traverse(
+            Ray3d::new(translation, direction),
+            PlayerPlugin::REACH,
+            &blocks,
+        ) {
+            Some(hit) => {
+                *transform = Transform::from_translation(hit.pos.as_vec3() + Vec3::splat(0.5));
                 *visibility = Visibility::Visible;
                 let material = materials.get_mut(handle).unwrap();
-                material.block_id = block_id as u32;
+                material.block_id = u8::from(hit.block).into();
             }
             None => {
                 *visibility = Visibility::Hidden;
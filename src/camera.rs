@@ -7,7 +7,7 @@ use crate::{
     physics::{PhysicsSet, Sprinting},
     player::{CameraAction, Player},
     sets::GameplaySet,
-    settings::{self, FOV},
+    settings::Settings,
     state::AppState,
 };
 
@@ -34,21 +34,25 @@ impl Plugin for CameraPlugin {
 impl CameraPlugin {
     const EYE_HEIGHT: f32 = 1.6;
 
-    fn spawn_camera(mut commands: Commands) {
-        commands.spawn(Camera3dBundle {
-            projection: PerspectiveProjection {
-                fov: settings::FOV,
+    fn spawn_camera(mut commands: Commands, settings: Res<Settings>) {
+        commands.spawn((
+            Camera3dBundle {
+                projection: PerspectiveProjection {
+                    fov: settings.fov_radians(),
+                    ..Default::default()
+                }
+                .into(),
+                tonemapping: Tonemapping::None,
                 ..Default::default()
-            }
-            .into(),
-            tonemapping: Tonemapping::None,
-            ..Default::default()
-        });
+            },
+            StateScoped(AppState::InGame),
+        ));
     }
 
     fn tilt_camera(
         mut q_camera: Query<&mut Transform, With<Camera>>,
         q_action: Query<&ActionState<CameraAction>>,
+        settings: Res<Settings>,
     ) {
         let mut camera = q_camera.single_mut();
         let action_state = q_action.single();
@@ -62,7 +66,7 @@ impl CameraPlugin {
         }
 
         let (_, mut pitch, _) = camera.rotation.to_euler(EulerRot::YXZ);
-        pitch -= delta.to_radians() * settings::SENSITIVITY;
+        pitch -= delta.to_radians() * settings.sensitivity;
         pitch = pitch.clamp(-FRAC_PI_2, FRAC_PI_2);
 
         camera.rotation = Quat::from_rotation_x(pitch);
@@ -92,25 +96,29 @@ impl CameraPlugin {
     pub(super) fn update_fov(
         mut q_proj: Query<&mut Projection>,
         q_sprinting: Query<Option<&Sprinting>, With<Player>>,
+        settings: Res<Settings>,
         time: Res<Time>,
     ) {
-        const SPRINTING_FOV: f32 = FOV * 1.1;
+        const SPRINTING_FOV_MULTIPLIER: f32 = 1.1;
         const FOV_CHANGE_DURATION: f32 = 0.15;
 
+        let normal_fov = settings.fov_radians();
+        let sprinting_fov = normal_fov * SPRINTING_FOV_MULTIPLIER;
+
         let mut projection = q_proj.single_mut();
         if let Projection::Perspective(PerspectiveProjection { fov, .. }) = &mut *projection {
             let is_sprinting = q_sprinting.single().is_some();
             let delta_seconds = time.delta_seconds();
 
             let (base_fov, target_fov) = if is_sprinting {
-                (FOV, SPRINTING_FOV)
+                (normal_fov, sprinting_fov)
             } else {
-                (SPRINTING_FOV, FOV)
+                (sprinting_fov, normal_fov)
             };
 
             let fov_diff = target_fov - base_fov;
             let fov_step = fov_diff * delta_seconds / FOV_CHANGE_DURATION;
-            *fov = (*fov + fov_step).clamp(FOV, SPRINTING_FOV);
+            *fov = (*fov + fov_step).clamp(normal_fov, sprinting_fov);
         }
     }
 }
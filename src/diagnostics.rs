@@ -34,7 +34,14 @@ impl Plugin for DiagnosticsPlugin {
                     .after(PhysicsSet)
                     .in_set(GameplaySet),
             )
-            .add_systems(Update, (Self::display_diagnostics).in_set(GameplaySet));
+            .add_systems(
+                Update,
+                (
+                    Self::display_diagnostics,
+                    Self::display_biome.run_if(resource_exists::<WorldgenParams>),
+                )
+                    .in_set(GameplaySet),
+            );
     }
 }
 
@@ -45,7 +52,7 @@ impl DiagnosticsPlugin {
     const BLOCKS_PER_SECOND: DiagnosticPath = DiagnosticPath::const_new("blocks_per_second");
     const HILLINESS: DiagnosticPath = DiagnosticPath::const_new("hilliness");
 
-    fn spawn_diagnostics_text(mut commands: Commands) {
+    fn spawn_diagnostics_text(mut commands: Commands, noise: Res<Noise>) {
         let text_style = TextStyle {
             font_size: 24.0,
             ..Default::default()
@@ -61,6 +68,10 @@ impl DiagnosticsPlugin {
                 TextSection::from_style(text_style.clone()),
                 TextSection::new("Hilliness: ", text_style.clone()),
                 TextSection::from_style(text_style.clone()),
+                TextSection::new("Biome: ", text_style.clone()),
+                TextSection::from_style(text_style.clone()),
+                TextSection::new("Seed: ", text_style.clone()),
+                TextSection::new(format!("{}\n", noise.seed()), text_style.clone()),
             ])
             .with_style(Style {
                 position_type: PositionType::Absolute,
@@ -69,6 +80,7 @@ impl DiagnosticsPlugin {
                 ..Default::default()
             }),
             DiagnosticsText,
+            StateScoped(AppState::InGame),
         ));
     }
 
@@ -147,4 +159,18 @@ impl DiagnosticsPlugin {
         text.sections[5].value = format!("{bps:.4}\n");
         text.sections[7].value = format!("{hilliness:.4}\n");
     }
+
+    fn display_biome(
+        mut q_text: Query<&mut Text, With<DiagnosticsText>>,
+        q_player: Query<&PhysicalPosition, With<Player>>,
+        noise: Res<Noise>,
+        params: Res<WorldgenParams>,
+    ) {
+        let mut text = q_text.single_mut();
+        let pos = q_player.single().current().xz().round().as_dvec2();
+
+        let biome = params.biome(&noise, pos);
+        let climate = noise.climate(pos);
+        text.sections[9].value = format!("{} ({:.2}/{:.2})\n", biome.name(), climate.x, climate.y);
+    }
 }
@@ -1,7 +1,7 @@
 use bevy::prelude::*;
 use strum::EnumIter;
 
-#[derive(EnumIter, Clone, Copy, Debug)]
+#[derive(EnumIter, Clone, Copy, PartialEq, Eq, Debug)]
 pub(super) enum Direction {
     North,
     South,
@@ -51,3 +51,19 @@ impl From<Direction> for IVec3 {
         }
     }
 }
+
+#[rustfmt::skip]
+impl TryFrom<IVec3> for Direction {
+    type Error = &'static str;
+    fn try_from(dir: IVec3) -> Result<Self, Self::Error> {
+        match dir {
+            IVec3 { x:  0, y:  0, z:  1 } => Ok(Direction::North),
+            IVec3 { x:  0, y:  0, z: -1 } => Ok(Direction::South),
+            IVec3 { x:  1, y:  0, z:  0 } => Ok(Direction::West),
+            IVec3 { x: -1, y:  0, z:  0 } => Ok(Direction::East),
+            IVec3 { x:  0, y:  1, z:  0 } => Ok(Direction::Up),
+            IVec3 { x:  0, y: -1, z:  0 } => Ok(Direction::Down),
+            _ => Err("Invalid direction"),
+        }
+    }
+}
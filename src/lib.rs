@@ -0,0 +1,190 @@
+#![allow(clippy::type_complexity, clippy::too_many_arguments)]
+
+use std::path::Path;
+
+use bevy::{
+    asset::load_internal_asset,
+    prelude::*,
+    window::{CursorGrabMode, PrimaryWindow},
+};
+use block::BlocksPlugin;
+use block_overlay::BlockOverlayPlugin;
+use camera::CameraPlugin;
+use crosshair::CrosshairPlugin;
+use diagnostics::DiagnosticsPlugin;
+use loading_screen::LoadingScreenPlugin;
+use materials::{BlockOverlayMaterial, ChunkMaterial};
+use menu::MenuPlugin;
+use physics::PhysicsPlugin;
+use player::PlayerPlugin;
+use sets::{GameplaySet, LoadingSet};
+use settings::{Settings, SettingsPlugin};
+use settings_screen::SettingsScreenPlugin;
+use state::{AppState, GameState};
+use textures::TexturesPlugin;
+use toml_asset::{TomlAsset, TomlLoader};
+use world::{ChunkArea, Save, WorldPlugin};
+
+mod block;
+mod block_overlay;
+mod camera;
+mod crosshair;
+mod diagnostics;
+mod direction;
+mod loading_screen;
+mod materials;
+mod menu;
+mod physics;
+mod player;
+mod sets;
+mod settings;
+mod settings_screen;
+mod state;
+mod textures;
+mod toml_asset;
+mod world;
+
+pub const UTILS_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(131465340603768031182789503363035378691);
+pub const DIRECTION_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(40560788717271163742317989904110872029);
+
+/// Runs the game. Takes the name of a world to open instead of showing the main menu, and its
+/// seed, as optional arguments.
+pub fn run() {
+    let mut args = std::env::args().skip(1);
+    let save = args.next().map(|name| {
+        let save = Save::new(name);
+        match args.next() {
+            Some(seed) => save.with_seed(&seed),
+            None => save,
+        }
+    });
+
+    let mut app = App::new();
+    app.register_asset_source(Settings::SOURCE, Settings::asset_source())
+        .add_plugins(DefaultPlugins.set(ImagePlugin::default_nearest()));
+    if let Some(save) = save {
+        app.insert_resource(save);
+    }
+    app.init_asset::<TomlAsset>()
+        .init_asset_loader::<TomlLoader>()
+        .add_plugins((
+            BlockOverlayPlugin,
+            BlocksPlugin,
+            CameraPlugin,
+            CrosshairPlugin,
+            DiagnosticsPlugin,
+            LoadingScreenPlugin,
+            MaterialPlugin::<BlockOverlayMaterial>::default(),
+            MaterialPlugin::<ChunkMaterial>::default(),
+            MenuPlugin,
+            PhysicsPlugin,
+            PlayerPlugin,
+            SettingsPlugin,
+            SettingsScreenPlugin,
+            TexturesPlugin,
+            WorldPlugin,
+        ))
+        .init_state::<AppState>()
+        .add_sub_state::<GameState>()
+        .enable_state_scoped_entities::<AppState>()
+        .enable_state_scoped_entities::<GameState>()
+        .configure_sets(
+            FixedUpdate,
+            GameplaySet.run_if(in_state(GameState::Playing)),
+        )
+        .configure_sets(
+            Update,
+            (
+                GameplaySet.run_if(in_state(GameState::Playing)),
+                LoadingSet.run_if(in_state(AppState::Loading)),
+            ),
+        )
+        .insert_resource(Msaa::Off)
+        .add_systems(OnEnter(AppState::Loading), spawn_loading_camera)
+        .add_systems(OnEnter(GameState::Playing), grab_cursor)
+        .add_systems(OnExit(GameState::Playing), release_cursor)
+        .add_systems(
+            Update,
+            finish_loading
+                .run_if(in_state(AppState::Loading))
+                .run_if(SettingsPlugin::is_loaded)
+                .run_if(TexturesPlugin::is_loaded)
+                .run_if(BlocksPlugin::is_loaded)
+                .run_if(WorldPlugin::is_loaded),
+        )
+        .add_systems(
+            Update,
+            start_game
+                .run_if(in_state(AppState::Generating))
+                .run_if(WorldPlugin::is_generated),
+        );
+
+    load_internal_asset!(app, UTILS_HANDLE, "utils.wgsl", Shader::from_wgsl);
+    load_internal_asset!(app, DIRECTION_HANDLE, "direction.wgsl", Shader::from_wgsl);
+
+    app.run();
+}
+
+/// Pre-generates chunks of a world without running the game.
+pub fn pregen() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let (Some(name), Some(area)) = (args.next(), args.next()) else {
+        anyhow::bail!("usage: vxl-pregen <world> <radius | x1,z1:x2,z2> [seed]");
+    };
+
+    let mut save = Save::new(name);
+    if let Some(seed) = args.next() {
+        save = save.with_seed(&seed);
+    }
+    world::pregenerate(&save, area.parse::<ChunkArea>()?)
+}
+
+/// Writes top-down maps of generated chunks, see [`world::export_maps`].
+pub fn map() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let (Some(seed), Some(area)) = (args.next(), args.next()) else {
+        anyhow::bail!(
+            "usage: vxl-map <seed> <radius | x1,z1:x2,z2> [worldgen.toml] [output directory]"
+        );
+    };
+    let worldgen = args.next().unwrap_or_else(|| "assets/worldgen.toml".into());
+    let out = args.next().unwrap_or_else(|| "maps".into());
+
+    world::export_maps(
+        world::parse_seed(&seed),
+        area.parse::<ChunkArea>()?,
+        Path::new(&worldgen),
+        Path::new(&out),
+    )
+}
+
+fn grab_cursor(mut query: Query<&mut Window, With<PrimaryWindow>>) {
+    let mut window = query.single_mut();
+    window.cursor.visible = false;
+    window.cursor.grab_mode = CursorGrabMode::Locked;
+}
+
+fn release_cursor(mut query: Query<&mut Window, With<PrimaryWindow>>) {
+    let mut window = query.single_mut();
+    window.cursor.visible = true;
+    window.cursor.grab_mode = CursorGrabMode::None;
+}
+
+/// Camera for the problems with the assets reported while loading.
+fn spawn_loading_camera(mut commands: Commands) {
+    commands.spawn((Camera2dBundle::default(), StateScoped(AppState::Loading)));
+}
+
+/// Opens the world given on the command line, or shows the main menu.
+fn finish_loading(save: Option<Res<Save>>, mut next_state: ResMut<NextState<AppState>>) {
+    next_state.set(match save {
+        Some(_) => AppState::Generating,
+        None => AppState::MainMenu,
+    });
+}
+
+fn start_game(mut next_state: ResMut<NextState<AppState>>) {
+    next_state.set(AppState::InGame);
+}
@@ -0,0 +1,99 @@
+use bevy::prelude::*;
+
+use crate::{state::AppState, world::GenerationProgress};
+
+#[derive(Component, Debug)]
+struct ProgressBar;
+
+#[derive(Component, Debug)]
+struct ProgressText;
+
+#[derive(Debug)]
+pub(super) struct LoadingScreenPlugin;
+
+impl Plugin for LoadingScreenPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::Generating), Self::spawn_loading_screen)
+            .add_systems(
+                Update,
+                (Self::update_loading_screen)
+                    .run_if(in_state(AppState::Generating))
+                    .run_if(resource_exists_and_changed::<GenerationProgress>),
+            );
+    }
+}
+
+impl LoadingScreenPlugin {
+    const BAR_WIDTH: f32 = 400.0;
+
+    fn spawn_loading_screen(mut commands: Commands) {
+        let text_style = TextStyle {
+            font_size: 24.0,
+            ..Default::default()
+        };
+
+        commands.spawn((Camera2dBundle::default(), StateScoped(AppState::Generating)));
+        commands
+            .spawn((
+                NodeBundle {
+                    style: Style {
+                        width: Val::Percent(100.0),
+                        height: Val::Percent(100.0),
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        justify_content: JustifyContent::Center,
+                        row_gap: Val::Px(10.0),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                StateScoped(AppState::Generating),
+            ))
+            .with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    "Generating world",
+                    text_style.clone(),
+                ));
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            width: Val::Px(Self::BAR_WIDTH),
+                            height: Val::Px(20.0),
+                            ..Default::default()
+                        },
+                        background_color: Color::srgb(0.2, 0.2, 0.2).into(),
+                        ..Default::default()
+                    })
+                    .with_children(|bar| {
+                        bar.spawn((
+                            NodeBundle {
+                                style: Style {
+                                    width: Val::Percent(0.0),
+                                    height: Val::Percent(100.0),
+                                    ..Default::default()
+                                },
+                                background_color: Color::srgb(0.3, 0.7, 0.3).into(),
+                                ..Default::default()
+                            },
+                            ProgressBar,
+                        ));
+                    });
+                parent.spawn((TextBundle::from_section("", text_style), ProgressText));
+            });
+    }
+
+    fn update_loading_screen(
+        progress: Res<GenerationProgress>,
+        mut q_bar: Query<&mut Style, With<ProgressBar>>,
+        mut q_text: Query<&mut Text, With<ProgressText>>,
+    ) {
+        q_bar.single_mut().width = Val::Percent(progress.fraction() * 100.0);
+        q_text.single_mut().sections[0].value = format!(
+            "{}/{} chunks generated, {}/{} meshed",
+            progress.generated(),
+            progress.target(),
+            progress.meshed(),
+            progress.target()
+        );
+    }
+}
@@ -10,18 +10,33 @@ use bevy::{
     },
 };
 
-// xxxxxxxxxxxx | xx       | xxx       | xxxxxxx | xxxx | xxxx
-//              | block id | direction | y       | z    | x
+use crate::block::BlockRegistry;
+
+// x | xxxx        | xxxx       | xxxxxxxx | xxx       | xxxx | xxxx | xxxx
+//   | quad height | quad width | block id | direction | y    | z    | x
 pub(super) const ATTRIBUTE_BLOCK_DATA: MeshVertexAttribute =
     MeshVertexAttribute::new("Data", 1000000, VertexFormat::Sint32);
 
+// xxxxxxxxxxxxxxxxxxx | xxx   | xx                | xxxx      | xxxx
+//                     | depth | ambient occlusion | sky light | block light
+// Depth lowers the top of the face, in ninths of a block, for the surface of flowing fluids.
+pub(super) const ATTRIBUTE_LIGHT: MeshVertexAttribute =
+    MeshVertexAttribute::new("Light", 1000001, VertexFormat::Uint32);
+
 #[derive(Asset, TypePath, AsBindGroup, Clone, Debug)]
+#[bind_group_data(ChunkMaterialKey)]
 pub(super) struct ChunkMaterial {
     #[texture(0, dimension = "2d_array")]
     #[sampler(1)]
     texture: Handle<Image>,
-    #[uniform(2)]
-    offset: IVec2,
+    #[storage(2, read_only)]
+    texture_layers: Vec<u32>,
+    translucent: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub(super) struct ChunkMaterialKey {
+    translucent: bool,
 }
 
 #[derive(Asset, TypePath, AsBindGroup, Clone, Debug)]
@@ -34,23 +49,39 @@ pub(super) struct BlockOverlayMaterial {
     pub(super) blocks_texture: Handle<Image>,
     #[uniform(4)]
     pub(super) block_id: u32,
+    #[storage(5, read_only)]
+    pub(super) texture_layers: Vec<u32>,
 }
 
 impl ChunkMaterial {
-    pub(super) fn new(offset: IVec2, texture: &Handle<Image>) -> Self {
+    pub(super) fn new(texture: &Handle<Image>, blocks: &BlockRegistry, translucent: bool) -> Self {
         Self {
-            offset,
             texture: texture.clone(),
+            texture_layers: blocks.texture_layers(),
+            translucent,
         }
     }
 }
 
 impl BlockOverlayMaterial {
-    pub(super) fn new(overlay_texture: &Handle<Image>, blocks_texture: &Handle<Image>) -> Self {
+    pub(super) fn new(
+        overlay_texture: &Handle<Image>,
+        blocks_texture: &Handle<Image>,
+        blocks: &BlockRegistry,
+    ) -> Self {
         Self {
             overlay_texture: overlay_texture.clone(),
             blocks_texture: blocks_texture.clone(),
             block_id: u32::MAX,
+            texture_layers: blocks.texture_layers(),
+        }
+    }
+}
+
+impl From<&ChunkMaterial> for ChunkMaterialKey {
+    fn from(material: &ChunkMaterial) -> Self {
+        Self {
+            translucent: material.translucent,
         }
     }
 }
@@ -64,16 +95,30 @@ impl Material for ChunkMaterial {
         "shaders/chunk.wgsl".into()
     }
 
+    fn alpha_mode(&self) -> AlphaMode {
+        if self.translucent {
+            AlphaMode::Blend
+        } else {
+            AlphaMode::Opaque
+        }
+    }
+
     fn specialize(
         _pipeline: &MaterialPipeline<Self>,
         descriptor: &mut RenderPipelineDescriptor,
         layout: &MeshVertexBufferLayoutRef,
-        _key: MaterialPipelineKey<Self>,
+        key: MaterialPipelineKey<Self>,
     ) -> Result<(), SpecializedMeshPipelineError> {
-        let vertex_layout = layout
-            .0
-            .get_layout(&[ATTRIBUTE_BLOCK_DATA.at_shader_location(0)])?;
+        let vertex_layout = layout.0.get_layout(&[
+            ATTRIBUTE_BLOCK_DATA.at_shader_location(0),
+            ATTRIBUTE_LIGHT.at_shader_location(1),
+        ])?;
         descriptor.vertex.buffers = vec![vertex_layout];
+        if key.bind_group_data.translucent {
+            if let Some(fragment) = descriptor.fragment.as_mut() {
+                fragment.shader_defs.push("TRANSLUCENT".into());
+            }
+        }
         Ok(())
     }
 }
@@ -0,0 +1,186 @@
+use bevy::{app::AppExit, prelude::*};
+
+use crate::{
+    state::{AppState, GameState},
+    world::Save,
+};
+
+#[derive(Component, Clone, Debug)]
+enum MenuButton {
+    NewWorld,
+    LoadWorld(Save),
+    Quit,
+    Resume,
+    Settings,
+    QuitToMenu,
+}
+
+#[derive(Debug)]
+pub(super) struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::MainMenu), Self::spawn_main_menu)
+            .add_systems(OnEnter(GameState::Paused), Self::spawn_pause_menu)
+            .add_systems(
+                Update,
+                (
+                    Self::toggle_pause.run_if(in_state(AppState::InGame)),
+                    (Self::highlight_buttons, Self::handle_menu_buttons),
+                )
+                    .chain(),
+            );
+    }
+}
+
+impl MenuPlugin {
+    const BUTTON_COLOR: Color = Color::srgb(0.2, 0.2, 0.2);
+    const HOVERED_COLOR: Color = Color::srgb(0.3, 0.3, 0.3);
+    const BACKGROUND_COLOR: Color = Color::srgba(0.0, 0.0, 0.0, 0.8);
+
+    fn spawn_main_menu(mut commands: Commands) {
+        commands.spawn((Camera2dBundle::default(), StateScoped(AppState::MainMenu)));
+
+        let saves = Save::list();
+        commands
+            .spawn((menu_root(), StateScoped(AppState::MainMenu)))
+            .with_children(|parent| {
+                parent.spawn(title("vxl"));
+                spawn_button(parent, "New world", MenuButton::NewWorld);
+                for save in saves {
+                    spawn_button(
+                        parent,
+                        &format!("Load `{}`", save.name()),
+                        MenuButton::LoadWorld(save),
+                    );
+                }
+                spawn_button(parent, "Quit", MenuButton::Quit);
+            });
+    }
+
+    fn spawn_pause_menu(mut commands: Commands) {
+        commands
+            .spawn((menu_root(), StateScoped(GameState::Paused)))
+            .with_children(|parent| {
+                parent.spawn(title("Paused"));
+                spawn_button(parent, "Resume", MenuButton::Resume);
+                spawn_button(parent, "Settings", MenuButton::Settings);
+                spawn_button(parent, "Quit to menu", MenuButton::QuitToMenu);
+            });
+    }
+
+    fn toggle_pause(
+        keys: Res<ButtonInput<KeyCode>>,
+        state: Res<State<GameState>>,
+        mut next_state: ResMut<NextState<GameState>>,
+    ) {
+        if !keys.just_pressed(KeyCode::Escape) {
+            return;
+        }
+
+        match state.get() {
+            GameState::Playing => next_state.set(GameState::Paused),
+            GameState::Paused => next_state.set(GameState::Playing),
+            GameState::Settings => {}
+        }
+    }
+
+    fn highlight_buttons(
+        mut query: Query<
+            (&Interaction, &mut BackgroundColor),
+            (Changed<Interaction>, With<MenuButton>),
+        >,
+    ) {
+        for (interaction, mut color) in &mut query {
+            *color = match interaction {
+                Interaction::None => Self::BUTTON_COLOR,
+                Interaction::Hovered | Interaction::Pressed => Self::HOVERED_COLOR,
+            }
+            .into();
+        }
+    }
+
+    fn handle_menu_buttons(
+        mut commands: Commands,
+        query: Query<(&Interaction, &MenuButton), Changed<Interaction>>,
+        mut next_app_state: ResMut<NextState<AppState>>,
+        mut next_game_state: ResMut<NextState<GameState>>,
+        mut exit: EventWriter<AppExit>,
+    ) {
+        for (interaction, button) in &query {
+            if *interaction != Interaction::Pressed {
+                continue;
+            }
+
+            match button {
+                MenuButton::NewWorld => {
+                    commands.insert_resource(Save::unused());
+                    next_app_state.set(AppState::Generating);
+                }
+                MenuButton::LoadWorld(save) => {
+                    commands.insert_resource(save.clone());
+                    next_app_state.set(AppState::Generating);
+                }
+                MenuButton::Quit => {
+                    exit.send(AppExit::Success);
+                }
+                MenuButton::Resume => next_game_state.set(GameState::Playing),
+                MenuButton::Settings => next_game_state.set(GameState::Settings),
+                MenuButton::QuitToMenu => next_app_state.set(AppState::MainMenu),
+            }
+        }
+    }
+}
+
+/// Full-screen node laying out its children in a centered column.
+fn menu_root() -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            row_gap: Val::Px(10.0),
+            ..Default::default()
+        },
+        background_color: MenuPlugin::BACKGROUND_COLOR.into(),
+        ..Default::default()
+    }
+}
+
+fn title(text: &str) -> TextBundle {
+    TextBundle::from_section(
+        text,
+        TextStyle {
+            font_size: 48.0,
+            ..Default::default()
+        },
+    )
+}
+
+fn spawn_button(parent: &mut ChildBuilder, text: &str, action: MenuButton) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(300.0),
+                    padding: UiRect::all(Val::Px(10.0)),
+                    justify_content: JustifyContent::Center,
+                    ..Default::default()
+                },
+                background_color: MenuPlugin::BUTTON_COLOR.into(),
+                ..Default::default()
+            },
+            action,
+        ))
+        .with_children(|button| {
+            button.spawn(TextBundle::from_section(
+                text,
+                TextStyle {
+                    font_size: 24.0,
+                    ..Default::default()
+                },
+            ));
+        });
+}
@@ -1,8 +1,8 @@
 use std::ops::{AddAssign, Mul};
 
-use bevy::prelude::*;
+use bevy::{math::bounding::Aabb3d, prelude::*};
 
-use crate::{sets::GameplaySet, world::Chunks};
+use crate::{block::BlockRegistry, sets::GameplaySet, world::Chunks};
 
 #[derive(Component, Default, Debug)]
 pub(super) struct PhysicalPosition {
@@ -25,6 +25,10 @@ pub(super) struct Grounded;
 #[derive(Component, Debug)]
 pub(super) struct Flying;
 
+/// The body is touching a fluid.
+#[derive(Component, Debug)]
+pub(super) struct Submerged;
+
 #[derive(Component, Debug)]
 pub(super) struct Sprinting {
     multiplier: f32,
@@ -73,6 +77,13 @@ impl RigidBody {
     pub(super) fn new(width: f32, height: f32) -> Self {
         Self(Cuboid::new(width, height, width))
     }
+
+    pub(super) fn aabb(&self, pos: Vec3) -> Aabb3d {
+        Aabb3d::new(
+            pos + Vec3::new(0.0, self.0.half_size.y, 0.0),
+            self.0.half_size,
+        )
+    }
 }
 
 impl Velocity {
@@ -128,11 +139,13 @@ impl Plugin for PhysicsPlugin {
                         Self::update_grounded,
                         Self::update_flying,
                         Self::update_sprinting,
+                        Self::update_submerged,
                     ),
                     (
                         Self::handle_collisions,
                         Self::apply_horizontal_drag,
                         Self::apply_vertical_drag,
+                        Self::apply_fluid_drag,
                     ),
                 )
                     .chain()
@@ -154,6 +167,8 @@ impl PhysicsPlugin {
     const VERTICAL_DRAG: f32 = 0.006;
     const SLIPPERINESS: f32 = 0.8;
     const FLIGHT_VELOCITY_REDUCTION: f32 = 0.1;
+    const SUBMERGED_GRAVITY_MULTIPLIER: f32 = 0.25;
+    const FLUID_DRAG: f32 = 0.08;
 
     fn remove_negligible_velocities(mut query: Query<&mut Velocity>) {
         const MIN_VELOCITY: f32 = 0.003;
@@ -177,10 +192,18 @@ impl PhysicsPlugin {
         }
     }
 
-    fn apply_gravity(mut query: Query<&mut Velocity, Without<Flying>>, time: Res<Time>) {
+    fn apply_gravity(
+        mut query: Query<(&mut Velocity, Option<&Submerged>), Without<Flying>>,
+        time: Res<Time>,
+    ) {
         let delta_seconds = time.delta_seconds();
-        for mut vel in &mut query {
-            vel.0.y -= Self::GRAVITY * delta_seconds;
+        for (mut vel, submerged) in &mut query {
+            let multiplier = if submerged.is_some() {
+                Self::SUBMERGED_GRAVITY_MULTIPLIER
+            } else {
+                1.0
+            };
+            vel.0.y -= Self::GRAVITY * multiplier * delta_seconds;
         }
     }
 
@@ -210,6 +233,7 @@ impl PhysicsPlugin {
         query: Query<(Entity, &PhysicalPosition, &RigidBody, &Velocity)>,
         time: Res<Time>,
         chunks: Res<Chunks>,
+        blocks: Res<BlockRegistry>,
         mut events: EventWriter<CollisionEvent>,
     ) {
         let delta_seconds = time.delta_seconds();
@@ -222,14 +246,14 @@ impl PhysicsPlugin {
             let mut pos = pos.current;
             let displacement = vel.0 * delta_seconds;
 
-            let collision_y = collision_at::<'Y'>(&mut pos, body, displacement, &chunks);
+            let collision_y = collision_at::<'Y'>(&mut pos, body, displacement, &chunks, &blocks);
             let (collision_x, collision_z) = if displacement.z.abs() > displacement.x.abs() {
-                let x = collision_at::<'X'>(&mut pos, body, displacement, &chunks);
-                let z = collision_at::<'Z'>(&mut pos, body, displacement, &chunks);
+                let x = collision_at::<'X'>(&mut pos, body, displacement, &chunks, &blocks);
+                let z = collision_at::<'Z'>(&mut pos, body, displacement, &chunks, &blocks);
                 (x, z)
             } else {
-                let z = collision_at::<'Z'>(&mut pos, body, displacement, &chunks);
-                let x = collision_at::<'X'>(&mut pos, body, displacement, &chunks);
+                let z = collision_at::<'Z'>(&mut pos, body, displacement, &chunks, &blocks);
+                let x = collision_at::<'X'>(&mut pos, body, displacement, &chunks, &blocks);
                 (x, z)
             };
 
@@ -322,6 +346,35 @@ impl PhysicsPlugin {
         }
     }
 
+    fn update_submerged(
+        mut commands: Commands,
+        query: Query<(Entity, &PhysicalPosition, &RigidBody)>,
+        chunks: Res<Chunks>,
+        blocks: Res<BlockRegistry>,
+    ) {
+        for (entity, pos, body) in &query {
+            let aabb = body.aabb(pos.current);
+            let min = Vec3::from(aabb.min).floor().as_ivec3();
+            let max = Vec3::from(aabb.max).ceil().as_ivec3();
+
+            let is_submerged = (min.x..max.x).any(|x| {
+                (min.y..max.y).any(|y| {
+                    (min.z..max.z).any(|z| {
+                        chunks
+                            .block_at(IVec3::new(x, y, z))
+                            .is_some_and(|block| blocks[block].is_fluid())
+                    })
+                })
+            });
+
+            if is_submerged {
+                commands.entity(entity).insert(Submerged);
+            } else {
+                commands.entity(entity).remove::<Submerged>();
+            }
+        }
+    }
+
     fn apply_horizontal_drag(mut query: Query<&mut Velocity>) {
         for mut vel in &mut query {
             vel.0.x *= 1.0 - Self::HORIZONTAL_DRAG;
@@ -335,6 +388,12 @@ impl PhysicsPlugin {
         }
     }
 
+    fn apply_fluid_drag(mut query: Query<&mut Velocity, With<Submerged>>) {
+        for mut vel in &mut query {
+            vel.0 *= 1.0 - Self::FLUID_DRAG;
+        }
+    }
+
     fn reduce_flight_velocity(mut query: Query<&mut Velocity, With<Flying>>) {
         for mut vel in &mut query {
             vel.0.y *= 1.0 - Self::FLIGHT_VELOCITY_REDUCTION;
@@ -364,6 +423,7 @@ fn collision_at<const AXIS: char>(
     body: &RigidBody,
     displacement: Vec3,
     chunks: &Chunks,
+    blocks: &BlockRegistry,
 ) -> Option<f32> {
     let (vel, displacement) = match AXIS {
         'X' => (displacement.x, Vec3::new(displacement.x, 0.0, 0.0)),
@@ -395,7 +455,7 @@ fn collision_at<const AXIS: char>(
             for y in min.y.floor() as i32..max.y.ceil() as i32 {
                 if chunks
                     .block_at(IVec3::new(x, y, z))
-                    .is_some_and(|block| block.is_solid())
+                    .is_some_and(|block| blocks[block].is_solid())
                 {
                     let (block_pos, body_pos) = match AXIS {
                         'X' => (
@@ -1,17 +1,18 @@
 use std::time::Duration;
 
-use bevy::{prelude::*, time::Stopwatch};
+use bevy::{math::bounding::Aabb3d, prelude::*, time::Stopwatch};
 use leafwing_input_manager::prelude::*;
 
 use crate::{
+    block::{BlockId, BlockRegistry},
     physics::{
         Acceleration, CollisionEvent, Flying, Grounded, MovementBundle, PhysicalPosition,
-        PhysicsSet, RigidBody, Sprinting, Velocity,
+        PhysicsSet, RigidBody, Sprinting, Submerged, Velocity,
     },
     sets::GameplaySet,
-    settings,
+    settings::{Controls, Settings},
     state::AppState,
-    world::CHUNK_WIDTH,
+    world::{BlockEditEvent, Chunks, WorldSpawn, CHUNK_WIDTH},
 };
 
 #[derive(Component, Default, Debug)]
@@ -38,12 +39,19 @@ enum MovementAction {
     Sprint,
 }
 
+#[derive(Actionlike, PartialEq, Eq, Hash, Clone, Reflect, Debug)]
+enum BlockAction {
+    Break,
+    Place,
+}
+
 #[derive(Bundle, Default)]
 struct PlayerBundle {
     player: Player,
     transform: TransformBundle,
     camera_action_manager: InputManagerBundle<CameraAction>,
     movement_action_manager: InputManagerBundle<MovementAction>,
+    block_action_manager: InputManagerBundle<BlockAction>,
     physical_position: PhysicalPosition,
     movement_bundle: MovementBundle,
     rigid_body: RigidBody,
@@ -58,23 +66,63 @@ impl PlayerChunkMoveEvent {
     }
 }
 
+impl CameraAction {
+    /// Mouse motion units the right stick turns by per frame when fully deflected.
+    const STICK_TURN_SPEED: f32 = 15.0;
+
+    fn input_map() -> InputMap<Self> {
+        InputMap::new([
+            (Self::Turn, DualAxis::mouse_motion()),
+            (
+                Self::Turn,
+                DualAxis::right_stick()
+                    .with_sensitivity(Self::STICK_TURN_SPEED, Self::STICK_TURN_SPEED)
+                    .inverted_y(),
+            ),
+        ])
+    }
+}
+
+impl MovementAction {
+    fn input_map(controls: &Controls) -> InputMap<Self> {
+        let mut map = InputMap::default();
+        for bindings in [&controls.keyboard, &controls.gamepad] {
+            for (action, binding) in [
+                (Self::Forward, bindings.forward),
+                (Self::Left, bindings.left),
+                (Self::Backward, bindings.backward),
+                (Self::Right, bindings.right),
+                (Self::Up, bindings.up),
+                (Self::Down, bindings.down),
+                (Self::Sprint, bindings.sprint),
+            ] {
+                binding.insert(&mut map, action);
+            }
+        }
+        map
+    }
+}
+
+impl BlockAction {
+    fn input_map(controls: &Controls) -> InputMap<Self> {
+        let mut map = InputMap::default();
+        for bindings in [&controls.keyboard, &controls.gamepad] {
+            bindings.break_block.insert(&mut map, Self::Break);
+            bindings.place_block.insert(&mut map, Self::Place);
+        }
+        map
+    }
+}
+
 impl PlayerBundle {
-    fn new(transform: Transform) -> Self {
+    fn new(transform: Transform, controls: &Controls) -> Self {
         Self {
             transform: transform.into(),
-            camera_action_manager: InputManagerBundle::with_map(InputMap::new([(
-                CameraAction::Turn,
-                DualAxis::mouse_motion(),
-            )])),
-            movement_action_manager: InputManagerBundle::with_map(InputMap::new([
-                (MovementAction::Forward, KeyCode::KeyW),
-                (MovementAction::Left, KeyCode::KeyA),
-                (MovementAction::Backward, KeyCode::KeyS),
-                (MovementAction::Right, KeyCode::KeyD),
-                (MovementAction::Up, KeyCode::Space),
-                (MovementAction::Down, KeyCode::ShiftLeft),
-                (MovementAction::Sprint, KeyCode::ControlLeft),
-            ])),
+            camera_action_manager: InputManagerBundle::with_map(CameraAction::input_map()),
+            movement_action_manager: InputManagerBundle::with_map(MovementAction::input_map(
+                controls,
+            )),
+            block_action_manager: InputManagerBundle::with_map(BlockAction::input_map(controls)),
             physical_position: transform.into(),
             rigid_body: RigidBody::new(0.6, 1.8),
             ..Default::default()
@@ -88,6 +136,7 @@ impl Plugin for PlayerPlugin {
             .add_plugins((
                 InputManagerPlugin::<CameraAction>::default(),
                 InputManagerPlugin::<MovementAction>::default(),
+                InputManagerPlugin::<BlockAction>::default(),
             ))
             .add_systems(OnEnter(AppState::InGame), Self::spawn_player)
             .add_systems(
@@ -97,6 +146,7 @@ impl Plugin for PlayerPlugin {
             .add_systems(
                 Update,
                 (
+                    Self::update_controls.run_if(resource_changed::<Settings>),
                     Self::turn_player,
                     Self::handle_player_horizontal_movement,
                     Self::handle_player_flight,
@@ -104,23 +154,51 @@ impl Plugin for PlayerPlugin {
                 )
                     .chain()
                     .in_set(GameplaySet),
+            )
+            .add_systems(
+                Update,
+                (Self::handle_block_actions)
+                    .after(PhysicsSet)
+                    .in_set(GameplaySet),
             );
     }
 }
 
 impl PlayerPlugin {
+    pub(super) const REACH: f32 = 4.5;
     const ACCELERATION: f32 = 64.0;
     const JUMP_VELOCITY: f32 = 10.0;
+    const SWIM_VELOCITY: f32 = 4.0;
     const AUTOJUMP_COOLDOWN: Duration = Duration::from_millis(500);
     const DOUBLE_TAP_DELAY: Duration = Duration::from_millis(500);
     const SPRINT_MULTIPLIER: f32 = 1.5;
+    const PLACED_BLOCK: &'static str = "stone";
 
-    fn spawn_player(mut commands: Commands) {
-        let pos = Vec3::new(0.0, 60.0, 0.0);
-        commands.spawn(PlayerBundle::new(Transform::from_translation(pos)));
+    fn spawn_player(mut commands: Commands, settings: Res<Settings>, spawn: Res<WorldSpawn>) {
+        commands.spawn((
+            PlayerBundle::new(
+                Transform::from_translation(spawn.position()),
+                &settings.controls,
+            ),
+            StateScoped(AppState::InGame),
+        ));
+    }
+
+    fn update_controls(
+        settings: Res<Settings>,
+        mut query: Query<(&mut InputMap<MovementAction>, &mut InputMap<BlockAction>), With<Player>>,
+    ) {
+        let Ok((mut movement, mut block)) = query.get_single_mut() else {
+            return;
+        };
+        *movement = MovementAction::input_map(&settings.controls);
+        *block = BlockAction::input_map(&settings.controls);
     }
 
-    fn turn_player(mut query: Query<(&mut Transform, &ActionState<CameraAction>), With<Player>>) {
+    fn turn_player(
+        mut query: Query<(&mut Transform, &ActionState<CameraAction>), With<Player>>,
+        settings: Res<Settings>,
+    ) {
         let (mut player, action_state) = query.single_mut();
         let delta = action_state
             .axis_pair(&CameraAction::Turn)
@@ -131,7 +209,7 @@ impl PlayerPlugin {
         }
 
         let (mut yaw, _, _) = player.rotation.to_euler(EulerRot::YXZ);
-        yaw -= delta.to_radians() * settings::SENSITIVITY;
+        yaw -= delta.to_radians() * settings.sensitivity;
 
         player.rotation = Quat::from_rotation_y(yaw);
     }
@@ -242,6 +320,7 @@ impl PlayerPlugin {
                 &ActionState<MovementAction>,
                 &mut Velocity,
                 Option<&Grounded>,
+                Option<&Submerged>,
             ),
             With<Player>,
         >,
@@ -249,18 +328,63 @@ impl PlayerPlugin {
         mut cooldown: Local<Stopwatch>,
     ) {
         cooldown.tick(time.delta());
-        let (action_state, mut vel, grounded) = query.single_mut();
+        let (action_state, mut vel, grounded, submerged) = query.single_mut();
 
         if action_state.pressed(&MovementAction::Up) {
             if grounded.is_some() && cooldown.elapsed() >= Self::AUTOJUMP_COOLDOWN {
                 vel.0.y = Self::JUMP_VELOCITY;
                 cooldown.reset();
+            } else if submerged.is_some() {
+                vel.0.y = vel.0.y.max(Self::SWIM_VELOCITY);
             }
         } else {
             cooldown.set_elapsed(Self::AUTOJUMP_COOLDOWN);
         }
     }
 
+    fn handle_block_actions(
+        q_camera: Query<&Transform, With<Camera>>,
+        q_action: Query<&ActionState<BlockAction>, With<Player>>,
+        q_bodies: Query<(&PhysicalPosition, &RigidBody)>,
+        chunks: Res<Chunks>,
+        blocks: Res<BlockRegistry>,
+        mut events: EventWriter<BlockEditEvent>,
+    ) {
+        let camera = q_camera.single();
+        let action_state = q_action.single();
+
+        let ray = Ray3d::new(camera.translation, camera.rotation * Vec3::NEG_Z);
+        let Some(hit) = chunks.traverse(ray, Self::REACH, &blocks) else {
+            return;
+        };
+
+        if action_state.just_pressed(&BlockAction::Break) {
+            events.send(BlockEditEvent::new(hit.pos, BlockId::AIR));
+        } else if action_state.just_pressed(&BlockAction::Place) {
+            let target = hit.pos + IVec3::from(hit.face);
+            if chunks
+                .block_at(target)
+                .is_some_and(|block| blocks[block].is_solid())
+            {
+                return;
+            }
+
+            let block = Aabb3d::new(target.as_vec3() + Vec3::splat(0.5), Vec3::splat(0.5));
+            let obstructed = q_bodies.iter().any(|(pos, body)| {
+                let body = body.aabb(pos.current());
+                (body.min.cmplt(block.max) & body.max.cmpgt(block.min)).all()
+            });
+            if obstructed {
+                return;
+            }
+
+            let Some(block) = blocks.id(Self::PLACED_BLOCK) else {
+                return;
+            };
+            events.send(BlockEditEvent::new(target, block));
+        }
+    }
+
     fn player_chunk_move(
         query: Query<&PhysicalPosition, With<Player>>,
         mut events: EventWriter<PlayerChunkMoveEvent>,
@@ -1,5 +1,460 @@
-use std::f32::consts;
+use std::{fmt, fs, path::PathBuf, time::Duration};
 
-pub(super) const SENSITIVITY: f32 = 0.1;
-pub(super) const FOV: f32 = 90.0_f32 * consts::PI / 180.0;
-pub(super) const RENDER_DISTANCE: i32 = 10;
+use bevy::{
+    asset::{
+        io::{file::FileAssetReader, AssetSource, AssetSourceBuilder},
+        AssetPath, LoadState,
+    },
+    prelude::*,
+    window::{PresentMode, PrimaryWindow, WindowMode},
+};
+use etcetera::{base_strategy::choose_native_strategy, BaseStrategy};
+use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize, Serializer};
+use strum::EnumIter;
+
+use crate::{sets::LoadingSet, toml_asset::TomlAsset};
+
+/// User settings from `settings.toml` in the user's config directory, or from the defaults in
+/// `assets/settings.toml` while it doesn't exist. Missing keys keep their default values, and
+/// changes to the file are applied while the game is running.
+#[derive(Resource, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct Settings {
+    /// Degrees turned per unit of mouse motion.
+    #[serde(serialize_with = "serialize_f32")]
+    pub(super) sensitivity: f32,
+    /// Vertical field of view, in degrees.
+    #[serde(serialize_with = "serialize_f32")]
+    pub(super) fov: f32,
+    /// Distance in chunks up to which chunks are loaded.
+    pub(super) render_distance: i32,
+    pub(super) vsync: bool,
+    pub(super) window_mode: WindowMode,
+    pub(super) controls: Controls,
+}
+
+/// Every action is bound to one input on the keyboard or mouse, and one on a gamepad. Turning is
+/// always bound to the mouse and the right stick.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub(super) struct Controls {
+    #[serde(default = "Bindings::keyboard")]
+    pub(super) keyboard: Bindings,
+    #[serde(default = "Bindings::gamepad")]
+    pub(super) gamepad: Bindings,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub(super) struct Bindings {
+    pub(super) forward: Binding,
+    pub(super) backward: Binding,
+    pub(super) left: Binding,
+    pub(super) right: Binding,
+    pub(super) up: Binding,
+    pub(super) down: Binding,
+    pub(super) sprint: Binding,
+    pub(super) break_block: Binding,
+    pub(super) place_block: Binding,
+}
+
+#[derive(EnumIter, Clone, Copy, PartialEq, Eq, Debug)]
+pub(super) enum Action {
+    Forward,
+    Backward,
+    Left,
+    Right,
+    Up,
+    Down,
+    Sprint,
+    BreakBlock,
+    PlaceBlock,
+}
+
+/// Input bound to an action, written as e.g. `{ key = "KeyW" }`, `{ mouse = "Left" }`,
+/// `{ gamepad = "South" }` or `{ left_stick = "up" }`.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButtonType),
+    LeftStick(StickDirection),
+}
+
+#[derive(Serialize, Deserialize, EnumIter, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum StickDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Both files are loaded, so that the user's one is picked up once it is first saved.
+#[derive(Resource, Debug)]
+struct LoadingSettings {
+    user: Handle<TomlAsset>,
+    defaults: Handle<TomlAsset>,
+    is_loaded: bool,
+}
+
+#[derive(Debug)]
+pub(super) struct SettingsPlugin;
+
+impl Settings {
+    pub(super) const MAX_RENDER_DISTANCE: i32 = 32;
+    /// Asset source reading from the user's config directory.
+    pub(super) const SOURCE: &'static str = "config";
+    const FILE_NAME: &'static str = "settings.toml";
+
+    pub(super) fn fov_radians(&self) -> f32 {
+        self.fov.to_radians()
+    }
+
+    /// Path of the user's `settings.toml`, e.g. `~/.config/vxl/settings.toml` on Linux. Falls back
+    /// to the game directory if the user has no home directory.
+    pub(super) fn path() -> PathBuf {
+        Self::dir().join(Self::FILE_NAME)
+    }
+
+    /// Writes the settings to the user's `settings.toml`.
+    pub(super) fn save(&self) -> anyhow::Result<()> {
+        fs::create_dir_all(Self::dir())?;
+        fs::write(Self::path(), toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Asset source of the user's config directory. It has to be registered before the
+    /// `AssetPlugin`. The directory is created so that it can be watched for changes.
+    pub(super) fn asset_source() -> AssetSourceBuilder {
+        let dir = Self::dir();
+        let path = dir.to_string_lossy().into_owned();
+        let source =
+            AssetSource::build().with_reader(AssetSource::get_default_reader(path.clone()));
+        match fs::create_dir_all(&dir) {
+            Ok(()) => source.with_watcher(AssetSource::get_default_watcher(
+                path,
+                Duration::from_millis(300),
+            )),
+            Err(_) => source,
+        }
+    }
+
+    fn dir() -> PathBuf {
+        match choose_native_strategy() {
+            Ok(strategy) => strategy.config_dir().join("vxl"),
+            Err(_) => FileAssetReader::get_base_path(),
+        }
+    }
+
+    fn from_toml(table: &TomlAsset) -> Result<Self, String> {
+        let settings = Self::deserialize(toml::Value::Table(table.0.clone()))
+            .map_err(|err| err.to_string())?;
+
+        if settings.sensitivity <= 0.0 {
+            return Err("`sensitivity` must be positive".into());
+        }
+        if !(1.0..180.0).contains(&settings.fov) {
+            return Err("`fov` must be in 1..180".into());
+        }
+        if !(1..=Self::MAX_RENDER_DISTANCE).contains(&settings.render_distance) {
+            return Err(format!(
+                "`render_distance` must be in 1..={}",
+                Self::MAX_RENDER_DISTANCE
+            ));
+        }
+
+        Ok(settings)
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            sensitivity: 0.1,
+            fov: 90.0,
+            render_distance: 10,
+            vsync: true,
+            window_mode: WindowMode::Windowed,
+            controls: Controls::default(),
+        }
+    }
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Self {
+            keyboard: Bindings::keyboard(),
+            gamepad: Bindings::gamepad(),
+        }
+    }
+}
+
+impl Bindings {
+    fn keyboard() -> Self {
+        Self {
+            forward: Binding::Key(KeyCode::KeyW),
+            backward: Binding::Key(KeyCode::KeyS),
+            left: Binding::Key(KeyCode::KeyA),
+            right: Binding::Key(KeyCode::KeyD),
+            up: Binding::Key(KeyCode::Space),
+            down: Binding::Key(KeyCode::ShiftLeft),
+            sprint: Binding::Key(KeyCode::ControlLeft),
+            break_block: Binding::Mouse(MouseButton::Left),
+            place_block: Binding::Mouse(MouseButton::Right),
+        }
+    }
+
+    fn gamepad() -> Self {
+        Self {
+            forward: Binding::LeftStick(StickDirection::Up),
+            backward: Binding::LeftStick(StickDirection::Down),
+            left: Binding::LeftStick(StickDirection::Left),
+            right: Binding::LeftStick(StickDirection::Right),
+            up: Binding::Gamepad(GamepadButtonType::South),
+            down: Binding::Gamepad(GamepadButtonType::East),
+            sprint: Binding::Gamepad(GamepadButtonType::LeftThumb),
+            break_block: Binding::Gamepad(GamepadButtonType::RightTrigger2),
+            place_block: Binding::Gamepad(GamepadButtonType::LeftTrigger2),
+        }
+    }
+
+    pub(super) fn get(&self, action: Action) -> Binding {
+        match action {
+            Action::Forward => self.forward,
+            Action::Backward => self.backward,
+            Action::Left => self.left,
+            Action::Right => self.right,
+            Action::Up => self.up,
+            Action::Down => self.down,
+            Action::Sprint => self.sprint,
+            Action::BreakBlock => self.break_block,
+            Action::PlaceBlock => self.place_block,
+        }
+    }
+
+    pub(super) fn set(&mut self, action: Action, binding: Binding) {
+        let slot = match action {
+            Action::Forward => &mut self.forward,
+            Action::Backward => &mut self.backward,
+            Action::Left => &mut self.left,
+            Action::Right => &mut self.right,
+            Action::Up => &mut self.up,
+            Action::Down => &mut self.down,
+            Action::Sprint => &mut self.sprint,
+            Action::BreakBlock => &mut self.break_block,
+            Action::PlaceBlock => &mut self.place_block,
+        };
+        *slot = binding;
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Forward => "Forward",
+            Self::Backward => "Backward",
+            Self::Left => "Left",
+            Self::Right => "Right",
+            Self::Up => "Jump / fly up",
+            Self::Down => "Fly down",
+            Self::Sprint => "Sprint",
+            Self::BreakBlock => "Break block",
+            Self::PlaceBlock => "Place block",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Binding {
+    /// Left stick deflection past which it counts as pressed in a direction.
+    pub(super) const STICK_THRESHOLD: f32 = 0.5;
+
+    pub(super) fn insert<A: Actionlike>(self, map: &mut InputMap<A>, action: A) {
+        match self {
+            Self::Key(key) => map.insert(action, key),
+            Self::Mouse(button) => map.insert(action, button),
+            Self::Gamepad(button) => map.insert(action, button),
+            Self::LeftStick(dir) => {
+                let threshold = Self::STICK_THRESHOLD;
+                map.insert(
+                    action,
+                    match dir {
+                        StickDirection::Up => {
+                            SingleAxis::positive_only(GamepadAxisType::LeftStickY, threshold)
+                        }
+                        StickDirection::Down => {
+                            SingleAxis::negative_only(GamepadAxisType::LeftStickY, -threshold)
+                        }
+                        StickDirection::Left => {
+                            SingleAxis::negative_only(GamepadAxisType::LeftStickX, -threshold)
+                        }
+                        StickDirection::Right => {
+                            SingleAxis::positive_only(GamepadAxisType::LeftStickX, threshold)
+                        }
+                    },
+                )
+            }
+        };
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Key(key) => write!(f, "{key:?}"),
+            Self::Mouse(button) => write!(f, "Mouse {button:?}"),
+            Self::Gamepad(button) => write!(f, "{button:?}"),
+            Self::LeftStick(dir) => write!(f, "Left stick {dir:?}"),
+        }
+    }
+}
+
+impl FromWorld for LoadingSettings {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world.resource::<AssetServer>();
+
+        LoadingSettings {
+            user: asset_server
+                .load(AssetPath::from(Settings::FILE_NAME).with_source(Settings::SOURCE)),
+            defaults: asset_server.load(Settings::FILE_NAME),
+            is_loaded: false,
+        }
+    }
+}
+
+impl LoadingSettings {
+    /// Handle of the file the settings are read from.
+    fn current(&self) -> &Handle<TomlAsset> {
+        if Settings::path().exists() {
+            &self.user
+        } else {
+            &self.defaults
+        }
+    }
+}
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LoadingSettings>()
+            .add_systems(Update, (Self::create_settings).in_set(LoadingSet))
+            .add_systems(
+                Update,
+                (
+                    Self::reload_settings,
+                    Self::apply_window_settings.run_if(resource_changed::<Settings>),
+                )
+                    .chain()
+                    .run_if(resource_exists::<Settings>),
+            );
+    }
+}
+
+impl SettingsPlugin {
+    pub(super) fn is_loaded(settings: Option<Res<Settings>>) -> bool {
+        settings.is_some()
+    }
+
+    /// Creates the settings once `settings.toml` is loaded, falling back to [`Settings::default`]
+    /// if it is invalid.
+    fn create_settings(
+        mut commands: Commands,
+        asset_server: Res<AssetServer>,
+        mut loading_settings: ResMut<LoadingSettings>,
+        toml_assets: Res<Assets<TomlAsset>>,
+    ) {
+        if loading_settings.is_loaded {
+            return;
+        }
+
+        let handle = loading_settings.current();
+        let settings = match asset_server.load_state(handle) {
+            LoadState::Loaded => Settings::from_toml(toml_assets.get(handle).unwrap()),
+            LoadState::Failed(err) => Err(err.to_string()),
+            _ => return,
+        };
+        loading_settings.is_loaded = true;
+
+        commands.insert_resource(settings.unwrap_or_else(|err| {
+            error!("invalid `settings.toml`, using the defaults: {err}");
+            Settings::default()
+        }));
+    }
+
+    fn reload_settings(
+        mut events: EventReader<AssetEvent<TomlAsset>>,
+        loading_settings: Res<LoadingSettings>,
+        toml_assets: Res<Assets<TomlAsset>>,
+        mut settings: ResMut<Settings>,
+    ) {
+        for ev in events.read() {
+            // The user's file is added rather than modified when it is first created.
+            let handle = loading_settings.current();
+            if !ev.is_modified(handle) && !ev.is_added(handle) {
+                continue;
+            }
+
+            let table = toml_assets.get(handle).unwrap();
+            match Settings::from_toml(table) {
+                Ok(new_settings) => {
+                    settings.set_if_neq(new_settings);
+                    info!("reloaded `settings.toml`");
+                }
+                Err(err) => error!("invalid `settings.toml`, keeping the previous version: {err}"),
+            }
+        }
+    }
+
+    fn apply_window_settings(
+        settings: Res<Settings>,
+        mut query: Query<&mut Window, With<PrimaryWindow>>,
+    ) {
+        let mut window = query.single_mut();
+        window.present_mode = if settings.vsync {
+            PresentMode::AutoVsync
+        } else {
+            PresentMode::AutoNoVsync
+        };
+        window.mode = settings.window_mode;
+    }
+}
+
+/// Writes the shortest decimal representation of the value, instead of that of its `f64`
+/// conversion, which `toml` would use.
+fn serialize_f32<S: Serializer>(value: &f32, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(value.to_string().parse().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn defaults_match_asset() {
+        let table = TomlAsset::read(Path::new("assets/settings.toml")).unwrap();
+        assert_eq!(Settings::from_toml(&table).unwrap(), Settings::default());
+    }
+
+    #[test]
+    fn saved_settings_round_trip() {
+        let mut settings = Settings {
+            sensitivity: 0.25,
+            fov: 75.0,
+            render_distance: 16,
+            vsync: false,
+            window_mode: WindowMode::BorderlessFullscreen,
+            ..Default::default()
+        };
+        settings
+            .controls
+            .gamepad
+            .set(Action::Sprint, Binding::LeftStick(StickDirection::Down));
+
+        let table = toml::from_str(&toml::to_string(&settings).unwrap()).unwrap();
+        assert_eq!(Settings::from_toml(&TomlAsset(table)).unwrap(), settings);
+    }
+}
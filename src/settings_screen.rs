@@ -0,0 +1,441 @@
+use std::fmt;
+
+use bevy::prelude::*;
+use strum::{EnumIter, IntoEnumIterator};
+
+use crate::{
+    settings::{Action, Binding, Bindings, Settings, StickDirection},
+    state::{AppState, GameState},
+};
+
+/// Setting changed with a pair of buttons on the settings screen.
+#[derive(EnumIter, Clone, Copy, PartialEq, Eq, Debug)]
+enum SettingOption {
+    Fov,
+    Sensitivity,
+    RenderDistance,
+    Vsync,
+}
+
+#[derive(Component, Clone, Copy, Debug)]
+struct OptionButton {
+    option: SettingOption,
+    step: i32,
+}
+
+#[derive(Component, Clone, Copy, Debug)]
+struct OptionValue(SettingOption);
+
+/// Binding of an action that can be changed on the settings screen.
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+struct BindingSlot {
+    action: Action,
+    gamepad: bool,
+}
+
+/// Slot waiting for the next pressed input.
+#[derive(Resource, Default, Debug)]
+struct Rebinding(Option<BindingSlot>);
+
+#[derive(Component, Debug)]
+struct SettingsMessage;
+
+#[derive(Debug)]
+pub(super) struct SettingsScreenPlugin;
+
+impl SettingOption {
+    fn adjust(self, settings: &mut Settings, step: i32) {
+        match self {
+            Self::Fov => settings.fov = (settings.fov + 5.0 * step as f32).clamp(30.0, 120.0),
+            Self::Sensitivity => {
+                let hundredths = (settings.sensitivity * 100.0).round() + step as f32;
+                settings.sensitivity = hundredths.clamp(1.0, 100.0) / 100.0;
+            }
+            Self::RenderDistance => {
+                settings.render_distance =
+                    (settings.render_distance + step).clamp(1, Settings::MAX_RENDER_DISTANCE)
+            }
+            Self::Vsync => settings.vsync = !settings.vsync,
+        }
+    }
+
+    fn value(self, settings: &Settings) -> String {
+        match self {
+            Self::Fov => format!("{}°", settings.fov),
+            Self::Sensitivity => format!("{:.2}", settings.sensitivity),
+            Self::RenderDistance => format!("{} chunks", settings.render_distance),
+            Self::Vsync => if settings.vsync { "On" } else { "Off" }.into(),
+        }
+    }
+}
+
+impl fmt::Display for SettingOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Fov => "Field of view",
+            Self::Sensitivity => "Sensitivity",
+            Self::RenderDistance => "Render distance",
+            Self::Vsync => "VSync",
+        };
+        f.write_str(name)
+    }
+}
+
+impl BindingSlot {
+    fn bindings(self, settings: &Settings) -> &Bindings {
+        if self.gamepad {
+            &settings.controls.gamepad
+        } else {
+            &settings.controls.keyboard
+        }
+    }
+
+    fn bindings_mut(self, settings: &mut Settings) -> &mut Bindings {
+        if self.gamepad {
+            &mut settings.controls.gamepad
+        } else {
+            &mut settings.controls.keyboard
+        }
+    }
+}
+
+impl Plugin for SettingsScreenPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Rebinding>()
+            .add_systems(OnEnter(GameState::Settings), Self::spawn_settings_screen)
+            .add_systems(OnExit(GameState::Settings), Self::cancel_rebinding)
+            .add_systems(
+                Update,
+                (
+                    Self::toggle_settings_screen.run_if(in_state(AppState::InGame)),
+                    (
+                        Self::capture_binding,
+                        (Self::select_binding, Self::adjust_options),
+                        (
+                            Self::update_binding_buttons.run_if(
+                                resource_changed::<Settings>.or_else(resource_changed::<Rebinding>),
+                            ),
+                            Self::update_option_values.run_if(resource_changed::<Settings>),
+                        ),
+                    )
+                        .chain()
+                        .run_if(in_state(GameState::Settings)),
+                )
+                    .chain(),
+            );
+    }
+}
+
+impl SettingsScreenPlugin {
+    const TOGGLE_KEY: KeyCode = KeyCode::F1;
+    const BUTTON_COLOR: Color = Color::srgb(0.2, 0.2, 0.2);
+    const REBINDING_COLOR: Color = Color::srgb(0.4, 0.4, 0.1);
+    const HINT: &'static str = "Click a binding to change it. Escape to go back.";
+
+    fn toggle_settings_screen(
+        keys: Res<ButtonInput<KeyCode>>,
+        rebinding: Res<Rebinding>,
+        state: Res<State<GameState>>,
+        mut next_state: ResMut<NextState<GameState>>,
+    ) {
+        if rebinding.0.is_some() {
+            return;
+        }
+
+        match state.get() {
+            GameState::Playing if keys.just_pressed(Self::TOGGLE_KEY) => {
+                next_state.set(GameState::Settings)
+            }
+            GameState::Settings if keys.just_pressed(Self::TOGGLE_KEY) => {
+                next_state.set(GameState::Playing)
+            }
+            GameState::Settings if keys.just_pressed(KeyCode::Escape) => {
+                next_state.set(GameState::Paused)
+            }
+            _ => {}
+        }
+    }
+
+    fn spawn_settings_screen(mut commands: Commands, settings: Res<Settings>) {
+        let text_style = TextStyle {
+            font_size: 24.0,
+            ..Default::default()
+        };
+        let cell = |width: f32| Style {
+            width: Val::Px(width),
+            padding: UiRect::all(Val::Px(5.0)),
+            justify_content: JustifyContent::Center,
+            ..Default::default()
+        };
+        let row = || NodeBundle {
+            style: Style {
+                column_gap: Val::Px(5.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let label = |text: &str| {
+            TextBundle::from_section(text, text_style.clone()).with_style(Style {
+                width: Val::Px(200.0),
+                padding: UiRect::all(Val::Px(5.0)),
+                ..Default::default()
+            })
+        };
+        let button = |width: f32| ButtonBundle {
+            style: cell(width),
+            background_color: Self::BUTTON_COLOR.into(),
+            ..Default::default()
+        };
+
+        commands
+            .spawn((
+                NodeBundle {
+                    style: Style {
+                        width: Val::Percent(100.0),
+                        height: Val::Percent(100.0),
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        justify_content: JustifyContent::Center,
+                        row_gap: Val::Px(5.0),
+                        ..Default::default()
+                    },
+                    background_color: Color::srgba(0.0, 0.0, 0.0, 0.8).into(),
+                    ..Default::default()
+                },
+                StateScoped(GameState::Settings),
+            ))
+            .with_children(|parent| {
+                parent.spawn(TextBundle::from_section("Settings", text_style.clone()));
+
+                for option in SettingOption::iter() {
+                    parent.spawn(row()).with_children(|row| {
+                        row.spawn(label(&option.to_string()));
+                        for (step, text) in [(-1, "<"), (1, ">")] {
+                            row.spawn((button(50.0), OptionButton { option, step }))
+                                .with_children(|button| {
+                                    button
+                                        .spawn(TextBundle::from_section(text, text_style.clone()));
+                                });
+                            if step < 0 {
+                                row.spawn(NodeBundle {
+                                    style: cell(150.0),
+                                    ..Default::default()
+                                })
+                                .with_children(|value| {
+                                    value.spawn((
+                                        TextBundle::from_section(
+                                            option.value(&settings),
+                                            text_style.clone(),
+                                        ),
+                                        OptionValue(option),
+                                    ));
+                                });
+                            }
+                        }
+                    });
+                }
+
+                parent.spawn(row()).with_children(|row| {
+                    row.spawn(label("Controls"));
+                    for column in ["Keyboard and mouse", "Gamepad"] {
+                        row.spawn(
+                            TextBundle::from_section(column, text_style.clone())
+                                .with_style(cell(250.0)),
+                        );
+                    }
+                });
+
+                for action in Action::iter() {
+                    parent.spawn(row()).with_children(|row| {
+                        row.spawn(label(&action.to_string()));
+                        for gamepad in [false, true] {
+                            let slot = BindingSlot { action, gamepad };
+                            row.spawn((button(250.0), slot)).with_children(|button| {
+                                button.spawn(TextBundle::from_section(
+                                    slot.bindings(&settings).get(action).to_string(),
+                                    text_style.clone(),
+                                ));
+                            });
+                        }
+                    });
+                }
+
+                parent.spawn((
+                    TextBundle::from_section(Self::HINT, text_style.clone()),
+                    SettingsMessage,
+                ));
+            });
+    }
+
+    fn cancel_rebinding(mut rebinding: ResMut<Rebinding>) {
+        rebinding.0 = None;
+    }
+
+    fn adjust_options(
+        mut settings: ResMut<Settings>,
+        rebinding: Res<Rebinding>,
+        q_button: Query<(&Interaction, &OptionButton), Changed<Interaction>>,
+        mut q_message: Query<&mut Text, With<SettingsMessage>>,
+    ) {
+        // A click that finished rebinding in this frame must not also press a button.
+        if rebinding.0.is_some() || rebinding.is_changed() {
+            return;
+        }
+
+        for (interaction, button) in &q_button {
+            if *interaction == Interaction::Pressed {
+                button.option.adjust(&mut settings, button.step);
+                save_settings(&settings, &mut q_message.single_mut().sections[0].value);
+            }
+        }
+    }
+
+    fn select_binding(
+        mut rebinding: ResMut<Rebinding>,
+        q_button: Query<(&Interaction, &BindingSlot), Changed<Interaction>>,
+        mut q_message: Query<&mut Text, With<SettingsMessage>>,
+    ) {
+        // A click that finished rebinding in this frame must not also press a button.
+        if rebinding.0.is_some() || rebinding.is_changed() {
+            return;
+        }
+
+        for (interaction, slot) in &q_button {
+            if *interaction != Interaction::Pressed {
+                continue;
+            }
+
+            rebinding.0 = Some(*slot);
+            let input = if slot.gamepad {
+                "a gamepad button or move the left stick"
+            } else {
+                "a key or mouse button"
+            };
+            q_message.single_mut().sections[0].value =
+                format!("Press {input} for {}. Escape to cancel.", slot.action);
+        }
+    }
+
+    /// Binds the next pressed input to the selected slot, unless another action already uses it.
+    /// Runs before [`Self::select_binding`], so that the click selecting a slot isn't captured, and
+    /// buttons ignore the frame in which it finishes, so that a captured click isn't handled twice.
+    fn capture_binding(
+        mut rebinding: ResMut<Rebinding>,
+        mut settings: ResMut<Settings>,
+        keys: Res<ButtonInput<KeyCode>>,
+        mouse_buttons: Res<ButtonInput<MouseButton>>,
+        gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+        axes: Res<Axis<GamepadAxis>>,
+        gamepads: Res<Gamepads>,
+        mut query: Query<&mut Text, With<SettingsMessage>>,
+    ) {
+        let Some(slot) = rebinding.0 else {
+            return;
+        };
+        let message = &mut query.single_mut().sections[0].value;
+
+        if keys.just_pressed(KeyCode::Escape) {
+            rebinding.0 = None;
+            *message = Self::HINT.into();
+            return;
+        }
+
+        let binding = if slot.gamepad {
+            gamepad_buttons
+                .get_just_pressed()
+                .map(|button| Binding::Gamepad(button.button_type))
+                .next()
+                .or_else(|| {
+                    gamepads
+                        .iter()
+                        .find_map(|gamepad| left_stick_direction(&axes, gamepad))
+                        .map(Binding::LeftStick)
+                })
+        } else {
+            keys.get_just_pressed()
+                .map(|key| Binding::Key(*key))
+                .chain(mouse_buttons.get_just_pressed().map(|b| Binding::Mouse(*b)))
+                .next()
+        };
+        let Some(binding) = binding else {
+            return;
+        };
+        rebinding.0 = None;
+
+        let bindings = slot.bindings(&settings);
+        if let Some(other) =
+            Action::iter().find(|other| *other != slot.action && bindings.get(*other) == binding)
+        {
+            *message = format!("{binding} is already bound to {other}.");
+            return;
+        }
+
+        slot.bindings_mut(&mut settings).set(slot.action, binding);
+        *message = Self::HINT.into();
+        save_settings(&settings, message);
+    }
+
+    fn update_binding_buttons(
+        settings: Res<Settings>,
+        rebinding: Res<Rebinding>,
+        mut q_button: Query<(&BindingSlot, &Children, &mut BackgroundColor)>,
+        mut q_text: Query<&mut Text>,
+    ) {
+        for (slot, children, mut color) in &mut q_button {
+            let is_rebinding = rebinding.0 == Some(*slot);
+            *color = if is_rebinding {
+                Self::REBINDING_COLOR
+            } else {
+                Self::BUTTON_COLOR
+            }
+            .into();
+
+            let mut text = q_text.get_mut(children[0]).unwrap();
+            text.sections[0].value = if is_rebinding {
+                "...".into()
+            } else {
+                slot.bindings(&settings).get(slot.action).to_string()
+            };
+        }
+    }
+
+    fn update_option_values(settings: Res<Settings>, mut query: Query<(&mut Text, &OptionValue)>) {
+        for (mut text, value) in &mut query {
+            text.sections[0].value = value.0.value(&settings);
+        }
+    }
+}
+
+/// Writes the settings to disk, reporting a failure in the message of the screen.
+fn save_settings(settings: &Settings, message: &mut String) {
+    if let Err(err) = settings.save() {
+        error!("failed to save `{}`: {err}", Settings::path().display());
+        *message = format!("Failed to save the settings: {err}");
+    }
+}
+
+/// Direction in which the left stick of the gamepad is deflected past the binding threshold.
+fn left_stick_direction(axes: &Axis<GamepadAxis>, gamepad: Gamepad) -> Option<StickDirection> {
+    let axis = |axis_type| {
+        axes.get(GamepadAxis::new(gamepad, axis_type))
+            .unwrap_or_default()
+    };
+    let x = axis(GamepadAxisType::LeftStickX);
+    let y = axis(GamepadAxisType::LeftStickY);
+
+    if x.abs().max(y.abs()) < Binding::STICK_THRESHOLD {
+        None
+    } else if y.abs() >= x.abs() {
+        Some(if y > 0.0 {
+            StickDirection::Up
+        } else {
+            StickDirection::Down
+        })
+    } else {
+        Some(if x > 0.0 {
+            StickDirection::Right
+        } else {
+            StickDirection::Left
+        })
+    }
+}
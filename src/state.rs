@@ -4,6 +4,17 @@ use bevy::prelude::*;
 pub(super) enum AppState {
     #[default]
     Loading,
+    MainMenu,
     Generating,
     InGame,
 }
+
+/// Screen shown while in game. Gameplay only runs while playing.
+#[derive(SubStates, Default, Debug, Clone, PartialEq, Eq, Hash)]
+#[source(AppState = AppState::InGame)]
+pub(super) enum GameState {
+    #[default]
+    Playing,
+    Paused,
+    Settings,
+}
@@ -4,6 +4,7 @@ use bevy::{
     render::{
         extract_resource::{ExtractResource, ExtractResourcePlugin},
         render_resource::Extent3d,
+        texture::{ImageAddressMode, ImageSampler, ImageSamplerDescriptor},
     },
 };
 
@@ -67,7 +68,7 @@ impl Plugin for TexturesPlugin {
 }
 
 impl TexturesPlugin {
-    const BLOCKS_TEXTURE_TILE_SIZE: usize = 8;
+    pub(super) const BLOCKS_TEXTURE_TILE_SIZE: usize = 8;
 
     pub(super) fn is_loaded(loading_textures: Res<LoadingTextures>) -> bool {
         loading_textures.blocks_loaded && loading_textures.crosshair_loaded
@@ -129,6 +130,11 @@ impl TexturesPlugin {
             height: Self::BLOCKS_TEXTURE_TILE_SIZE as u32,
             depth_or_array_layers: layers as u32,
         });
+        image.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
+            address_mode_u: ImageAddressMode::Repeat,
+            address_mode_v: ImageAddressMode::Repeat,
+            ..ImageSamplerDescriptor::nearest()
+        });
 
         commands.insert_resource(BlocksTexture(loading_textures.blocks.clone()));
     }
@@ -2,7 +2,7 @@ use bevy::{
     asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
     prelude::*,
 };
-use std::str;
+use std::{fs, path::Path, str};
 use toml::{map::Map, Table, Value};
 
 #[derive(Asset, TypePath, Debug)]
@@ -11,6 +11,13 @@ pub(super) struct TomlAsset(pub(super) Map<String, Value>);
 #[derive(Default, Debug)]
 pub(super) struct TomlLoader;
 
+impl TomlAsset {
+    /// Reads a file directly, for when there is no asset server.
+    pub(super) fn read(path: &Path) -> anyhow::Result<Self> {
+        Ok(TomlAsset(fs::read_to_string(path)?.parse::<Table>()?))
+    }
+}
+
 impl AssetLoader for TomlLoader {
     type Asset = TomlAsset;
     type Settings = ();
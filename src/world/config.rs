@@ -0,0 +1,422 @@
+use std::fmt;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{block::BlockRegistry, toml_asset::TomlAsset};
+
+use super::{WorldPlugin, CHUNK_HEIGHT};
+
+/// Contents of `worldgen.toml`. Top-level keys are optional only so that every missing one can be
+/// reported, see [`WorldgenConfig::from_toml`].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub(super) struct WorldgenConfig {
+    pub(super) height_bias: Option<f64>,
+    pub(super) hilliness: Option<Vec<[f64; 2]>>,
+    pub(super) sea_level: Option<i64>,
+    pub(super) biome_blend: Option<f64>,
+    pub(super) biomes: Option<Vec<BiomeConfig>>,
+    pub(super) caves: Option<CaveParams>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub(super) struct BiomeConfig {
+    pub(super) name: String,
+    pub(super) temperature: f64,
+    pub(super) humidity: f64,
+    pub(super) height: Vec<[f64; 2]>,
+    pub(super) surface: String,
+    pub(super) subsurface: String,
+    #[serde(default)]
+    pub(super) trees: f64,
+    #[serde(default)]
+    pub(super) boulders: f64,
+}
+
+/// Caves are carved out of stone where either the cheese noise is above `cheese_threshold`, or
+/// both spaghetti noises are within `spaghetti_width` of zero, which forms long tunnels.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub(super) struct CaveParams {
+    /// Lowest layer that can be carved.
+    pub(super) min_height: usize,
+    pub(super) cheese_frequency: f64,
+    pub(super) cheese_threshold: f64,
+    pub(super) spaghetti_frequency: f64,
+    pub(super) spaghetti_width: f64,
+}
+
+/// Problem with `worldgen.toml`. Keys of biomes are prefixed with the name of the biome.
+#[derive(Debug)]
+pub(crate) enum WorldgenError {
+    /// The file isn't valid TOML.
+    Load(String),
+    /// A value has the wrong type, or a key is unknown.
+    Invalid(toml::de::Error),
+    Missing(&'static str),
+    OutOfRange {
+        key: String,
+        value: f64,
+        range: &'static str,
+    },
+    NotPositive {
+        key: String,
+        value: f64,
+    },
+    EmptySpline(String),
+    /// Points of a spline are not sorted by their first coordinate.
+    UnsortedSpline(String),
+    NoBiomes,
+    FeatureChances(String),
+    UnknownBlock {
+        key: String,
+        name: String,
+    },
+    MissingBlock(&'static str),
+}
+
+/// Every problem found in the last loaded version of `worldgen.toml`.
+#[derive(Resource, Debug)]
+pub(crate) struct WorldgenErrors(pub(crate) Vec<WorldgenError>);
+
+#[derive(Component, Debug)]
+pub(super) struct WorldgenErrorsUi;
+
+impl WorldgenConfig {
+    /// Blocks that world generation places regardless of biomes.
+    const REQUIRED_BLOCKS: [&'static str; 5] = ["stone", "wood", "leaves", "water", "sand"];
+
+    /// Parses the config, reporting every problem with it that can be found.
+    pub(super) fn from_toml(
+        table: &TomlAsset,
+        blocks: &BlockRegistry,
+    ) -> Result<Self, WorldgenErrors> {
+        let config = Self::deserialize(toml::Value::Table(table.0.clone()))
+            .map_err(|err| WorldgenErrors(vec![WorldgenError::Invalid(err)]))?;
+
+        let mut errors = Vec::new();
+        config.validate(blocks, &mut errors);
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(WorldgenErrors(errors))
+        }
+    }
+
+    fn validate(&self, blocks: &BlockRegistry, errors: &mut Vec<WorldgenError>) {
+        match self.height_bias {
+            Some(value) if value <= 0.0 => errors.push(WorldgenError::NotPositive {
+                key: "height_bias".into(),
+                value,
+            }),
+            Some(_) => {}
+            None => errors.push(WorldgenError::Missing("height_bias")),
+        }
+
+        match &self.hilliness {
+            Some(points) => validate_spline("hilliness", points, errors),
+            None => errors.push(WorldgenError::Missing("hilliness")),
+        }
+
+        match self.sea_level {
+            Some(value) if !(0..CHUNK_HEIGHT as i64).contains(&value) => {
+                errors.push(WorldgenError::OutOfRange {
+                    key: "sea_level".into(),
+                    value: value as f64,
+                    range: "0..256",
+                })
+            }
+            Some(_) => {}
+            None => errors.push(WorldgenError::Missing("sea_level")),
+        }
+
+        match self.biome_blend {
+            Some(value) if value <= 0.0 => errors.push(WorldgenError::NotPositive {
+                key: "biome_blend".into(),
+                value,
+            }),
+            Some(_) => {}
+            None => errors.push(WorldgenError::Missing("biome_blend")),
+        }
+
+        match &self.biomes {
+            Some(biomes) if biomes.is_empty() => errors.push(WorldgenError::NoBiomes),
+            Some(biomes) => {
+                for biome in biomes {
+                    biome.validate(blocks, errors);
+                }
+            }
+            None => errors.push(WorldgenError::Missing("biomes")),
+        }
+
+        match &self.caves {
+            Some(caves) => caves.validate(errors),
+            None => errors.push(WorldgenError::Missing("caves")),
+        }
+
+        for name in Self::REQUIRED_BLOCKS {
+            if blocks.id(name).is_none() {
+                errors.push(WorldgenError::MissingBlock(name));
+            }
+        }
+    }
+}
+
+impl BiomeConfig {
+    fn validate(&self, blocks: &BlockRegistry, errors: &mut Vec<WorldgenError>) {
+        let key = |key: &str| format!("{}.{key}", self.name);
+
+        for (name, value) in [
+            ("temperature", self.temperature),
+            ("humidity", self.humidity),
+        ] {
+            if !(0.0..=1.0).contains(&value) {
+                errors.push(WorldgenError::OutOfRange {
+                    key: key(name),
+                    value,
+                    range: "0..=1",
+                });
+            }
+        }
+
+        validate_spline(&key("height"), &self.height, errors);
+
+        for (name, block) in [("surface", &self.surface), ("subsurface", &self.subsurface)] {
+            if blocks.id(block).is_none() {
+                errors.push(WorldgenError::UnknownBlock {
+                    key: key(name),
+                    name: block.clone(),
+                });
+            }
+        }
+
+        if self.trees < 0.0 || self.boulders < 0.0 || self.trees + self.boulders > 1.0 {
+            errors.push(WorldgenError::FeatureChances(self.name.clone()));
+        }
+    }
+}
+
+impl CaveParams {
+    fn validate(&self, errors: &mut Vec<WorldgenError>) {
+        if self.min_height >= CHUNK_HEIGHT {
+            errors.push(WorldgenError::OutOfRange {
+                key: "caves.min_height".into(),
+                value: self.min_height as f64,
+                range: "0..256",
+            });
+        }
+
+        for (key, value) in [
+            ("caves.cheese_frequency", self.cheese_frequency),
+            ("caves.spaghetti_frequency", self.spaghetti_frequency),
+        ] {
+            if value <= 0.0 {
+                errors.push(WorldgenError::NotPositive {
+                    key: key.into(),
+                    value,
+                });
+            }
+        }
+
+        if self.spaghetti_width < 0.0 {
+            errors.push(WorldgenError::OutOfRange {
+                key: "caves.spaghetti_width".into(),
+                value: self.spaghetti_width,
+                range: "0..",
+            });
+        }
+    }
+}
+
+impl fmt::Display for WorldgenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Load(err) => write!(f, "failed to load: {err}"),
+            Self::Invalid(err) => write!(f, "{}", err.message()),
+            Self::Missing(key) => write!(f, "`{key}` is required"),
+            Self::OutOfRange { key, value, range } => {
+                write!(f, "`{key}` must be in {range}, got {value}")
+            }
+            Self::NotPositive { key, value } => write!(f, "`{key}` must be positive, got {value}"),
+            Self::EmptySpline(key) => write!(f, "`{key}` needs at least one point"),
+            Self::UnsortedSpline(key) => write!(f, "points of `{key}` must be sorted"),
+            Self::NoBiomes => write!(f, "at least one biome is required"),
+            Self::FeatureChances(biome) => write!(
+                f,
+                "chances of features in `{biome}` must not be negative or add up to more than 1"
+            ),
+            Self::UnknownBlock { key, name } => {
+                write!(f, "`{key}` refers to unknown block `{name}`")
+            }
+            Self::MissingBlock(name) => write!(f, "`{name}` block is required"),
+        }
+    }
+}
+
+impl fmt::Display for WorldgenErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for err in &self.0 {
+            writeln!(f, "{err}")?;
+        }
+        Ok(())
+    }
+}
+
+impl WorldPlugin {
+    pub(super) fn display_worldgen_errors(
+        mut commands: Commands,
+        errors: Option<Res<WorldgenErrors>>,
+        query: Query<Entity, With<WorldgenErrorsUi>>,
+    ) {
+        for entity in &query {
+            commands.entity(entity).despawn_recursive();
+        }
+        let Some(errors) = errors else {
+            return;
+        };
+
+        let text_style = TextStyle {
+            font_size: 24.0,
+            ..Default::default()
+        };
+
+        commands.spawn((
+            TextBundle::from_sections([
+                TextSection::new("Invalid `worldgen.toml`:\n", text_style.clone()),
+                TextSection::new(errors.to_string(), text_style),
+            ])
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(5.0),
+                left: Val::Px(5.0),
+                ..Default::default()
+            }),
+            WorldgenErrorsUi,
+        ));
+    }
+
+    pub(super) fn despawn_worldgen_errors(
+        mut commands: Commands,
+        query: Query<Entity, With<WorldgenErrorsUi>>,
+    ) {
+        for entity in &query {
+            commands.entity(entity).despawn_recursive();
+        }
+        commands.remove_resource::<WorldgenErrors>();
+    }
+}
+
+/// Checks that a spline has points, all of them in the unit square and sorted.
+fn validate_spline(key: &str, points: &[[f64; 2]], errors: &mut Vec<WorldgenError>) {
+    if points.is_empty() {
+        errors.push(WorldgenError::EmptySpline(key.into()));
+    }
+
+    for &value in points.iter().flatten() {
+        if !(0.0..=1.0).contains(&value) {
+            errors.push(WorldgenError::OutOfRange {
+                key: key.into(),
+                value,
+                range: "0..=1",
+            });
+        }
+    }
+
+    if points.windows(2).any(|pair| pair[0][0] > pair[1][0]) {
+        errors.push(WorldgenError::UnsortedSpline(key.into()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_str(toml: &str) -> Result<WorldgenConfig, WorldgenErrors> {
+        let blocks = BlockRegistry::from_assets();
+        WorldgenConfig::from_toml(&TomlAsset(toml.parse().unwrap()), &blocks)
+    }
+
+    fn error_messages(toml: &str) -> Vec<String> {
+        from_str(toml)
+            .unwrap_err()
+            .0
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn assets_are_valid() {
+        let config = from_str(&std::fs::read_to_string("assets/worldgen.toml").unwrap());
+        assert!(config.is_ok());
+    }
+
+    #[test]
+    fn reports_unknown_keys() {
+        let errors = error_messages("height_bais = 3.0");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("unknown field `height_bais`"));
+
+        let errors = error_messages(
+            r#"
+            [[biomes]]
+            name = "plains"
+            temperature = 0.5
+            humidity = 0.5
+            height = [[0.0, 0.1]]
+            surface = "grass"
+            subsurface = "dirt"
+            flowers = 0.1
+            "#,
+        );
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("unknown field `flowers`"));
+    }
+
+    #[test]
+    fn reports_every_error() {
+        let errors = error_messages(
+            r#"
+            hilliness = [[0.5, 0.1], [0.2, 1.5]]
+            sea_level = 300
+            biome_blend = 0.0
+
+            [caves]
+            min_height = 256
+            cheese_frequency = 0.02
+            cheese_threshold = 0.35
+            spaghetti_frequency = -1.0
+            spaghetti_width = -0.1
+
+            [[biomes]]
+            name = "plains"
+            temperature = 1.5
+            humidity = 0.5
+            height = []
+            surface = "grass"
+            subsurface = "mud"
+            trees = 0.7
+            boulders = 0.5
+            "#,
+        );
+        assert_eq!(
+            errors,
+            [
+                "`height_bias` is required",
+                "`hilliness` must be in 0..=1, got 1.5",
+                "points of `hilliness` must be sorted",
+                "`sea_level` must be in 0..256, got 300",
+                "`biome_blend` must be positive, got 0",
+                "`plains.temperature` must be in 0..=1, got 1.5",
+                "`plains.height` needs at least one point",
+                "`plains.subsurface` refers to unknown block `mud`",
+                "chances of features in `plains` must not be negative or add up to more than 1",
+                "`caves.min_height` must be in 0..256, got 256",
+                "`caves.spaghetti_frequency` must be positive, got -1",
+                "`caves.spaghetti_width` must be in 0.., got -0.1",
+            ]
+        );
+    }
+}
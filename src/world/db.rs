@@ -1,20 +1,33 @@
-use std::{str::FromStr, sync::Arc};
+use std::{fs, path::Path, sync::Arc};
 
-use bevy::{prelude::*, tasks::block_on};
+use bevy::{prelude::*, tasks::block_on, utils::HashSet};
 use sqlx::{
-    migrate::MigrateDatabase,
     prelude::FromRow,
     sqlite::{
         SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteRow, SqliteSynchronous,
     },
-    Connection, QueryBuilder, Row, Sqlite, SqliteConnection, SqlitePool,
+    QueryBuilder, Row, Sqlite, SqlitePool,
 };
 
-use super::{Chunk, CHUNK_VOLUME};
+use crate::block::BlockId;
+
+use super::{
+    feature::PendingBlocks, save::Save, section::Section, Chunk, CHUNK_SECTIONS, CHUNK_VOLUME,
+};
 
 #[derive(Resource, Clone, Debug)]
 pub(super) struct Db(pub(super) SqlitePool);
 
+/// Encoding of the `blocks` column.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(i32)]
+enum BlocksFormat {
+    /// One byte per block, in the order expected by [`Chunk::from_blocks`].
+    Raw,
+    /// Palette-compressed sections, see [`Section::write_bytes`].
+    Paletted,
+}
+
 #[derive(sqlx::FromRow, Debug)]
 pub(super) struct ChunkRow {
     pub(super) x: i32,
@@ -24,73 +37,357 @@ pub(super) struct ChunkRow {
 }
 
 impl Db {
+    pub(super) async fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(4)
+            .connect_with(
+                SqliteConnectOptions::new()
+                    .filename(path)
+                    .create_if_missing(true)
+                    .journal_mode(SqliteJournalMode::Wal)
+                    .synchronous(SqliteSynchronous::Normal),
+            )
+            .await?;
+
+        sqlx::migrate!().run(&pool).await?;
+
+        Ok(Self(pool))
+    }
+
     pub(super) async fn insert_chunks<I>(&self, chunks: I)
     where
         I: IntoIterator<Item = (IVec2, Arc<Chunk>)>,
     {
+        let mut chunks = chunks.into_iter().peekable();
+        if chunks.peek().is_none() {
+            return;
+        }
+
         let mut query_builder: QueryBuilder<Sqlite> =
-            sqlx::QueryBuilder::new("insert into chunks (x, z, blocks) ");
+            sqlx::QueryBuilder::new("insert into chunks (x, z, format, blocks) ");
         query_builder.push_values(chunks, |mut b, (offset, chunk)| {
-            let blocks: Vec<_> = chunk.0.iter().map(|&block| block as u8).collect();
-            b.push_bind(offset.x).push_bind(offset.y).push_bind(blocks);
+            b.push_bind(offset.x)
+                .push_bind(offset.y)
+                .push_bind(BlocksFormat::Paletted as i32)
+                .push_bind(chunk.to_bytes());
         });
+        query_builder.push(
+            " on conflict (x, z) do update set format = excluded.format, blocks = excluded.blocks",
+        );
         let query = query_builder.build();
 
         query.execute(&self.0).await.unwrap();
     }
 
+    /// Marks saved chunks as edited by the player, so that they are kept when the world is
+    /// regenerated.
+    pub(super) async fn mark_edited<'a, I>(&self, offsets: I)
+    where
+        I: IntoIterator<Item = &'a IVec2>,
+    {
+        let mut offsets = offsets.into_iter().peekable();
+        if offsets.peek().is_none() {
+            return;
+        }
+
+        let mut query_builder: QueryBuilder<Sqlite> =
+            sqlx::QueryBuilder::new("update chunks set edited = 1 where (x, z) in");
+        query_builder.push_tuples(offsets, |mut b, offset| {
+            b.push_bind(offset.x).push_bind(offset.y);
+        });
+        query_builder.build().execute(&self.0).await.unwrap();
+    }
+
+    /// Removes all chunks that weren't edited by the player, along with the blocks left for them
+    /// by features of their neighbors. Blocks left for the kept chunks and for chunks that weren't
+    /// generated yet are kept. Returns the offsets of the chunks that were kept.
+    pub(super) async fn delete_generated_chunks(&self) -> HashSet<IVec2> {
+        let mut tx = self.0.begin().await.unwrap();
+
+        sqlx::query(
+            "delete from pending_blocks where (x, z) in (select x, z from chunks where edited = 0)",
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        sqlx::query("delete from chunks where edited = 0")
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        let kept = sqlx::query("select x, z from chunks")
+            .fetch_all(&mut *tx)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|row| IVec2::new(row.get("x"), row.get("z")))
+            .collect();
+
+        tx.commit().await.unwrap();
+        kept
+    }
+
     pub(super) async fn get_chunks<'a, I>(&self, offsets: I) -> Vec<ChunkRow>
     where
         I: IntoIterator<Item = &'a IVec2>,
     {
         let mut query_builder: QueryBuilder<Sqlite> =
-            sqlx::QueryBuilder::new("select x, z, blocks from chunks where (x, z) in");
+            sqlx::QueryBuilder::new("select x, z, format, blocks from chunks where (x, z) in");
         query_builder.push_tuples(offsets, |mut b, offset| {
             b.push_bind(offset.x).push_bind(offset.y);
         });
         let query = query_builder.build_query_as();
         query.fetch_all(&self.0).await.unwrap()
     }
+
+    /// Drops the blocks of features left for chunks edited by the player, so that features of
+    /// chunks generated later don't grow into them.
+    pub(super) async fn discard_pending_for_edited(&self, pending: &mut PendingBlocks) {
+        if pending.is_empty() {
+            return;
+        }
+
+        let mut query_builder: QueryBuilder<Sqlite> =
+            sqlx::QueryBuilder::new("select x, z from chunks where edited = 1 and (x, z) in");
+        query_builder.push_tuples(pending.keys(), |mut b, offset| {
+            b.push_bind(offset.x).push_bind(offset.y);
+        });
+        let edited = query_builder.build().fetch_all(&self.0).await.unwrap();
+
+        for row in edited {
+            pending.remove(&IVec2::new(row.get("x"), row.get("z")));
+        }
+    }
+
+    /// Stores blocks of features for chunks that aren't loaded, see [`Db::take_pending_blocks`].
+    pub(super) async fn insert_pending_blocks(&self, pending: PendingBlocks) {
+        if pending.is_empty() {
+            return;
+        }
+
+        let mut query_builder: QueryBuilder<Sqlite> =
+            sqlx::QueryBuilder::new("insert into pending_blocks (x, z, blocks) ");
+        query_builder.push_values(pending, |mut b, (offset, blocks)| {
+            b.push_bind(offset.x)
+                .push_bind(offset.y)
+                .push_bind(pending_to_bytes(&blocks));
+        });
+        let query = query_builder.build();
+
+        query.execute(&self.0).await.unwrap();
+    }
+
+    /// Removes and returns the blocks left for chunks by features of their neighbors.
+    pub(super) async fn take_pending_blocks<'a, I>(&self, offsets: I) -> PendingBlocks
+    where
+        I: IntoIterator<Item = &'a IVec2> + Clone,
+    {
+        if offsets.clone().into_iter().next().is_none() {
+            return PendingBlocks::new();
+        }
+
+        let mut tx = self.0.begin().await.unwrap();
+
+        let mut query_builder: QueryBuilder<Sqlite> =
+            sqlx::QueryBuilder::new("select x, z, blocks from pending_blocks where (x, z) in");
+        query_builder.push_tuples(offsets.clone(), |mut b, offset| {
+            b.push_bind(offset.x).push_bind(offset.y);
+        });
+        let rows = query_builder.build().fetch_all(&mut *tx).await.unwrap();
+
+        let mut pending = PendingBlocks::new();
+        for row in rows {
+            let offset = IVec2::new(row.get("x"), row.get("z"));
+            pending
+                .entry(offset)
+                .or_default()
+                .extend(pending_from_bytes(row.get("blocks")));
+        }
+
+        let mut query_builder: QueryBuilder<Sqlite> =
+            sqlx::QueryBuilder::new("delete from pending_blocks where (x, z) in");
+        query_builder.push_tuples(offsets, |mut b, offset| {
+            b.push_bind(offset.x).push_bind(offset.y);
+        });
+        query_builder.build().execute(&mut *tx).await.unwrap();
+
+        tx.commit().await.unwrap();
+        pending
+    }
+
+    /// Returns the seed of the world, storing a new one if the world was just created.
+    pub(super) async fn seed(&self, new_seed: impl FnOnce() -> u32) -> u32 {
+        let seed = sqlx::query_scalar::<_, i64>("select seed from world")
+            .fetch_optional(&self.0)
+            .await
+            .unwrap();
+        if let Some(seed) = seed {
+            return seed as u32;
+        }
+
+        let seed = new_seed();
+        sqlx::query("insert into world (id, seed) values (0, ?)")
+            .bind(seed as i64)
+            .execute(&self.0)
+            .await
+            .unwrap();
+        seed
+    }
+
+    /// Returns the block the player spawns on, unless it wasn't found yet.
+    pub(super) async fn spawn(&self) -> Option<IVec3> {
+        let row = sqlx::query("select spawn_x, spawn_y, spawn_z from world")
+            .fetch_optional(&self.0)
+            .await
+            .unwrap()?;
+        Some(IVec3::new(
+            row.get::<Option<i32>, _>("spawn_x")?,
+            row.get::<Option<i32>, _>("spawn_y")?,
+            row.get::<Option<i32>, _>("spawn_z")?,
+        ))
+    }
+
+    pub(super) async fn set_spawn(&self, spawn: IVec3) {
+        sqlx::query("update world set spawn_x = ?, spawn_y = ?, spawn_z = ?")
+            .bind(spawn.x)
+            .bind(spawn.y)
+            .bind(spawn.z)
+            .execute(&self.0)
+            .await
+            .unwrap();
+    }
+
+    pub(super) async fn get_chunk_offsets<'a, I>(&self, offsets: I) -> HashSet<IVec2>
+    where
+        I: IntoIterator<Item = &'a IVec2>,
+    {
+        let mut query_builder: QueryBuilder<Sqlite> =
+            sqlx::QueryBuilder::new("select x, z from chunks where (x, z) in");
+        query_builder.push_tuples(offsets, |mut b, offset| {
+            b.push_bind(offset.x).push_bind(offset.y);
+        });
+        let query = query_builder.build();
+        query
+            .fetch_all(&self.0)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|row| IVec2::new(row.get("x"), row.get("z")))
+            .collect()
+    }
+}
+
+impl FromWorld for Db {
+    fn from_world(world: &mut World) -> Self {
+        let save = world.resource::<Save>();
+        block_on(Self::open(&save.db_path()))
+            .unwrap_or_else(|err| panic!("failed to open world `{}`: {err:#}", save.name()))
+    }
 }
 
-impl Default for Db {
-    fn default() -> Self {
-        Self(block_on(async {
-            let schema = sqlx::query!("select sql from sqlite_master where type='table'")
-                .fetch_all(
-                    &mut SqliteConnection::connect(env!("DATABASE_URL"))
-                        .await
-                        .unwrap(),
-                )
-                .await
-                .unwrap();
-
-            Sqlite::create_database(":memory:").await.unwrap();
-            let pool = SqlitePoolOptions::new()
-                .max_connections(4)
-                .connect_with(
-                    SqliteConnectOptions::from_str(":memory:")
-                        .unwrap()
-                        .journal_mode(SqliteJournalMode::Wal)
-                        .synchronous(SqliteSynchronous::Normal),
-                )
-                .await
-                .unwrap();
-
-            for statement in schema {
-                if let Some(sql) = statement.sql {
-                    sqlx::query(&sql).execute(&pool).await.unwrap();
-                }
-            }
-
-            pool
-        }))
+impl Chunk {
+    pub(super) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for section in &self.sections {
+            section.write_bytes(&mut bytes);
+        }
+        bytes
+    }
+
+    fn from_bytes(mut bytes: &[u8]) -> Option<Self> {
+        let mut sections = Vec::with_capacity(CHUNK_SECTIONS);
+        for _ in 0..CHUNK_SECTIONS {
+            sections.push(Section::read_bytes(&mut bytes)?);
+        }
+        if !bytes.is_empty() {
+            return None;
+        }
+        Some(Self::from_sections(sections.try_into().unwrap()))
     }
 }
 
 impl FromRow<'_, SqliteRow> for Chunk {
     fn from_row(row: &SqliteRow) -> sqlx::Result<Self> {
-        let blocks: [u8; CHUNK_VOLUME] = row.try_get::<&[u8], _>("blocks")?.try_into().unwrap();
-        Ok(Self(blocks.map(|block| block.into())))
+        let format = row.try_get::<i32, _>("format")?;
+        let blocks = row.try_get::<&[u8], _>("blocks")?;
+
+        let chunk = if format == BlocksFormat::Raw as i32 {
+            <[u8; CHUNK_VOLUME]>::try_from(blocks)
+                .ok()
+                .map(|blocks| Self::from_blocks(&blocks.map(|block| block.into())))
+        } else if format == BlocksFormat::Paletted as i32 {
+            Self::from_bytes(blocks)
+        } else {
+            None
+        };
+
+        chunk.ok_or_else(|| sqlx::Error::Decode("invalid chunk blocks".into()))
+    }
+}
+
+/// Encodes pending blocks as 4 bytes each: the local position as `x, y, z`, then the block.
+fn pending_to_bytes(blocks: &[(IVec3, BlockId)]) -> Vec<u8> {
+    blocks
+        .iter()
+        .flat_map(|&(pos, block)| [pos.x as u8, pos.y as u8, pos.z as u8, block.into()])
+        .collect()
+}
+
+fn pending_from_bytes(bytes: &[u8]) -> impl Iterator<Item = (IVec3, BlockId)> + '_ {
+    bytes.chunks_exact(4).map(|b| {
+        (
+            IVec3::new(b[0] as i32, b[1] as i32, b[2] as i32),
+            b[3].into(),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regeneration_keeps_pending_blocks_of_kept_chunks() {
+        let dir = std::env::temp_dir().join(format!("vxl-db-test-{}", std::process::id()));
+        let db = block_on(Db::open(&dir.join("world.db"))).unwrap();
+
+        let (edited, generated, missing) = (IVec2::ZERO, IVec2::X, IVec2::Y);
+        let chunk = Arc::new(Chunk::from_blocks(&[BlockId::AIR; CHUNK_VOLUME]));
+        let block = (IVec3::ONE, BlockId::from(3));
+        block_on(async {
+            db.insert_chunks([(edited, chunk.clone()), (generated, chunk)])
+                .await;
+            db.insert_pending_blocks(
+                [edited, generated, missing]
+                    .into_iter()
+                    .map(|offset| (offset, vec![block]))
+                    .collect(),
+            )
+            .await;
+            db.mark_edited([edited].iter()).await;
+
+            assert_eq!(db.delete_generated_chunks().await, HashSet::from([edited]));
+            let pending = db
+                .take_pending_blocks([edited, generated, missing].iter())
+                .await;
+            assert_eq!(
+                pending,
+                [(edited, vec![block]), (missing, vec![block])]
+                    .into_iter()
+                    .collect()
+            );
+
+            let mut pending: PendingBlocks = [(edited, vec![block]), (generated, vec![block])]
+                .into_iter()
+                .collect();
+            db.discard_pending_for_edited(&mut pending).await;
+            assert_eq!(pending.keys().collect::<Vec<_>>(), [&generated]);
+
+            db.0.close().await;
+        });
+        fs::remove_dir_all(dir).unwrap();
     }
 }
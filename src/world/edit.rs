@@ -0,0 +1,41 @@
+use bevy::{prelude::*, tasks::block_on, utils::HashSet};
+
+use crate::block::BlockRegistry;
+
+use super::{db::Db, BlockEditEvent, Chunks, DirtyChunks, WorldPlugin};
+
+impl WorldPlugin {
+    pub(super) fn edit_blocks(
+        mut events: EventReader<BlockEditEvent>,
+        db: Res<Db>,
+        blocks: Res<BlockRegistry>,
+        mut chunks: ResMut<Chunks>,
+        mut dirty: ResMut<DirtyChunks>,
+    ) {
+        let mut edited = HashSet::new();
+
+        for &BlockEditEvent { pos, block } in events.read() {
+            if let Some(offset) = chunks.set_block_at(pos, block) {
+                dirty.insert_block(pos);
+                for pos in chunks.update_light(pos, &blocks) {
+                    dirty.insert_block(pos);
+                }
+                edited.insert(offset);
+            }
+        }
+
+        if edited.is_empty() {
+            return;
+        }
+
+        block_on(async {
+            db.insert_chunks(
+                edited
+                    .iter()
+                    .map(|offset| (*offset, chunks.0[offset].clone())),
+            )
+            .await;
+            db.mark_edited(edited.iter()).await;
+        });
+    }
+}
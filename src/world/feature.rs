@@ -0,0 +1,237 @@
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::block::{BlockId, BlockRegistry};
+
+use super::{
+    world_to_local, Chunk, Chunks, WorldgenParams, CHUNK_HEIGHT, CHUNK_VOLUME, CHUNK_WIDTH,
+};
+
+/// Blocks of features that reach into chunks other than the one they were generated in, keyed by
+/// the offset of the chunk they belong to, with positions local to it.
+pub(super) type PendingBlocks = HashMap<IVec2, Vec<(IVec3, BlockId)>>;
+
+/// Structure placed on top of the terrain. Features only ever replace air, so the blocks of a
+/// feature that reach into a neighboring chunk can be placed after it is generated.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(super) enum Feature {
+    Tree,
+    Boulder,
+}
+
+impl Feature {
+    const MIN_TREE_HEIGHT: i32 = 4;
+    const MAX_TREE_HEIGHT: i32 = 6;
+
+    /// Blocks of the feature relative to its root, the air block right above the surface. `hash`
+    /// picks the shape of the feature.
+    fn blocks(self, hash: u64, params: &WorldgenParams) -> Vec<(IVec3, BlockId)> {
+        let mut blocks = Vec::new();
+
+        match self {
+            Self::Tree => {
+                let height = Self::MIN_TREE_HEIGHT
+                    + (hash % (Self::MAX_TREE_HEIGHT - Self::MIN_TREE_HEIGHT + 1) as u64) as i32;
+
+                for y in 0..height {
+                    blocks.push((IVec3::new(0, y, 0), params.wood));
+                }
+
+                for y in height - 2..=height {
+                    let radius: i32 = if y == height { 1 } else { 2 };
+                    for x in -radius..=radius {
+                        for z in -radius..=radius {
+                            if x.abs() == radius && z.abs() == radius {
+                                continue;
+                            }
+                            blocks.push((IVec3::new(x, y, z), params.leaves));
+                        }
+                    }
+                }
+            }
+            Self::Boulder => {
+                let radius = 1 + (hash % 2) as i32;
+                for x in -radius..=radius {
+                    for y in -radius..=radius {
+                        for z in -radius..=radius {
+                            let pos = IVec3::new(x, y, z);
+                            if pos.length_squared() <= radius * radius + radius {
+                                blocks.push((pos, params.stone));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        blocks
+    }
+}
+
+impl Chunk {
+    /// Places features rooted in the chunk. Returns the blocks that belong to other chunks.
+    pub(super) fn place_features(
+        chunk: &mut [BlockId; CHUNK_VOLUME],
+        offset: IVec2,
+        features: &[(IVec3, Feature, u64)],
+        params: &WorldgenParams,
+    ) -> PendingBlocks {
+        let mut pending = PendingBlocks::new();
+
+        for &(root, feature, hash) in features {
+            for (pos, block) in feature.blocks(hash, params) {
+                let pos = root + pos;
+                if pos.y < 0 || pos.y >= CHUNK_HEIGHT as i32 {
+                    continue;
+                }
+
+                let (neighbor, pos) = world_to_local(pos);
+                if neighbor != IVec2::ZERO {
+                    pending
+                        .entry(offset + neighbor)
+                        .or_default()
+                        .push((pos, block));
+                    continue;
+                }
+
+                let i = pos.x as usize
+                    + pos.z as usize * CHUNK_WIDTH
+                    + pos.y as usize * CHUNK_WIDTH * CHUNK_WIDTH;
+                if chunk[i] == BlockId::AIR {
+                    chunk[i] = block;
+                }
+            }
+        }
+
+        pending
+    }
+
+    /// Places blocks left for the chunk by features of its neighbors. Returns whether any block
+    /// was placed.
+    pub(super) fn place_pending(&mut self, pending: &[(IVec3, BlockId)]) -> bool {
+        let mut placed = false;
+        for &(pos, block) in pending {
+            if self.get(pos) == BlockId::AIR {
+                self.set(pos, block);
+                placed = true;
+            }
+        }
+        placed
+    }
+}
+
+impl Chunks {
+    /// Places blocks left by features of another chunk into a loaded chunk, updating the light
+    /// around them. Returns `None` if the chunk is not loaded, otherwise the positions whose block
+    /// or light changed.
+    pub(super) fn place_pending(
+        &mut self,
+        offset: IVec2,
+        pending: &[(IVec3, BlockId)],
+        blocks: &BlockRegistry,
+    ) -> Option<HashSet<IVec3>> {
+        if !self.0.contains_key(&offset) {
+            return None;
+        }
+
+        let origin = IVec3::new(offset.x, 0, offset.y) * CHUNK_WIDTH as i32;
+        let mut changed = HashSet::new();
+        for &(pos, block) in pending {
+            let pos = origin + pos;
+            if self.block_at(pos) == Some(BlockId::AIR) {
+                self.set_block_at(pos, block);
+                changed.insert(pos);
+                changed.extend(self.update_light(pos, blocks));
+            }
+        }
+
+        Some(changed)
+    }
+}
+
+pub(super) fn merge_pending(pending: &mut PendingBlocks, other: PendingBlocks) {
+    for (offset, blocks) in other {
+        pending.entry(offset).or_default().extend(blocks);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::toml_asset::TomlAsset;
+
+    use super::{
+        super::{spawn::generate_chunks, Noise},
+        *,
+    };
+
+    /// Generates the chunks one at a time in the given order, like chunks loaded at different
+    /// times: blocks left for a generated chunk are placed into it, the others are kept until it
+    /// is generated.
+    fn generate_in_order(
+        order: &[IVec2],
+        noise: &Noise,
+        params: &WorldgenParams,
+        blocks: &BlockRegistry,
+    ) -> HashMap<IVec2, Chunk> {
+        let mut chunks = HashMap::new();
+        let mut stored = PendingBlocks::new();
+        for &offset in order {
+            let (mut generated, pending) = generate_chunks(&[offset], noise, params, blocks);
+            let mut chunk = generated.remove(&offset).unwrap();
+            if let Some(stored) = stored.remove(&offset) {
+                chunk.place_pending(&stored);
+            }
+            chunks.insert(offset, chunk);
+
+            for (offset, pending) in pending {
+                match chunks.get_mut(&offset) {
+                    Some(chunk) => {
+                        chunk.place_pending(&pending);
+                    }
+                    None => stored.entry(offset).or_default().extend(pending),
+                }
+            }
+        }
+        chunks
+    }
+
+    #[test]
+    fn features_across_borders_dont_depend_on_order() {
+        let blocks = BlockRegistry::from_assets();
+        let table = TomlAsset::read(Path::new("assets/worldgen.toml")).unwrap();
+        let params = WorldgenParams::from_toml(&table, &blocks).unwrap();
+        let noise = Noise::new(42);
+
+        // Two neighbors where the first has a tree whose leaves reach into the second.
+        let (a, b, leaves) = (-32..32)
+            .map(|x| IVec2::new(x, 0))
+            .find_map(|a| {
+                let b = a + IVec2::X;
+                let (_, pending) = Chunk::generate(a, &noise, &params, &blocks);
+                let leaves: Vec<_> = pending
+                    .get(&b)?
+                    .iter()
+                    .filter(|(_, block)| *block == params.leaves)
+                    .map(|(pos, _)| *pos)
+                    .collect();
+                (!leaves.is_empty()).then_some((a, b, leaves))
+            })
+            .expect("no tree crossing a chunk border");
+
+        let (together, _) = generate_chunks(&[a, b], &noise, &params, &blocks);
+        assert!(leaves
+            .iter()
+            .any(|pos| together[&b].get(*pos) == params.leaves));
+
+        for order in [[a, b], [b, a]] {
+            let chunks = generate_in_order(&order, &noise, &params, &blocks);
+            for offset in [a, b] {
+                assert_eq!(chunks[&offset].to_bytes(), together[&offset].to_bytes());
+            }
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use bevy::{
+    prelude::*,
+    tasks::block_on,
+    utils::{HashMap, HashSet},
+};
+use strum::IntoEnumIterator;
+
+use crate::{
+    block::{BlockId, BlockRegistry, Fluid},
+    direction::Direction,
+};
+
+use super::{db::Db, BlockEditEvent, Chunks, DirtyChunks, WorldPlugin, CHUNK_WIDTH};
+
+/// Fluid blocks that may flow, and blocks next to which fluid may have to recede.
+#[derive(Resource, Default, Debug)]
+pub(super) struct ActiveFluids(HashSet<IVec3>);
+
+impl ActiveFluids {
+    /// Activates a block and all blocks next to it.
+    fn insert(&mut self, pos: IVec3) {
+        self.0.insert(pos);
+        self.0
+            .extend(Direction::iter().map(|dir| pos + IVec3::from(dir)));
+    }
+}
+
+impl WorldPlugin {
+    /// Fixed updates between fluid updates.
+    const FLUID_TICK: u32 = 5;
+    const MAX_FLUID_UPDATES: usize = 4096;
+    const HORIZONTAL: [Direction; 4] = [
+        Direction::North,
+        Direction::South,
+        Direction::West,
+        Direction::East,
+    ];
+
+    pub(super) fn activate_fluids(
+        mut events: EventReader<BlockEditEvent>,
+        mut active: ResMut<ActiveFluids>,
+    ) {
+        for ev in events.read() {
+            active.insert(ev.pos);
+        }
+    }
+
+    pub(super) fn update_fluids(
+        db: Res<Db>,
+        blocks: Res<BlockRegistry>,
+        mut chunks: ResMut<Chunks>,
+        mut dirty: ResMut<DirtyChunks>,
+        mut active: ResMut<ActiveFluids>,
+        mut ticks: Local<u32>,
+    ) {
+        *ticks += 1;
+        if *ticks < Self::FLUID_TICK || active.0.is_empty() {
+            return;
+        }
+        *ticks = 0;
+
+        // Blocks next to unloaded chunks stay active until those are loaded, unless their own
+        // chunk is gone.
+        let is_loaded = |chunks: &Chunks, pos: IVec3| {
+            let offset = pos.xz().div_euclid(IVec2::splat(CHUNK_WIDTH as i32));
+            chunks.0.contains_key(&offset)
+        };
+        let mut updated = Vec::new();
+        active.0.retain(|&pos| {
+            if !is_loaded(&chunks, pos) {
+                return false;
+            }
+            if updated.len() >= Self::MAX_FLUID_UPDATES
+                || Direction::iter().any(|dir| !is_loaded(&chunks, pos + IVec3::from(dir)))
+            {
+                return true;
+            }
+            updated.push(pos);
+            false
+        });
+
+        let mut changes: HashMap<IVec3, BlockId> = HashMap::new();
+        let mut change = |pos: IVec3, block: BlockId| {
+            let level = |block: BlockId| blocks[block].fluid().map_or(0, Fluid::level);
+            let current = changes.entry(pos).or_insert(block);
+            if level(block) > level(*current) {
+                *current = block;
+            }
+        };
+
+        for pos in updated {
+            let Some(fluid) = chunks.block_at(pos).and_then(|block| blocks[block].fluid()) else {
+                continue;
+            };
+            let fluid_at = |pos| chunks.block_at(pos).and_then(|block| blocks[block].fluid());
+
+            let mut level = fluid.level();
+            if !fluid.is_source() {
+                // Flowing fluid is fed by fluid above it or by higher fluid next to it.
+                level = if fluid.is_same(fluid_at(pos + IVec3::Y)) {
+                    Fluid::MAX_LEVEL - 1
+                } else {
+                    Self::HORIZONTAL
+                        .into_iter()
+                        .filter_map(|dir| fluid_at(pos + IVec3::from(dir)))
+                        .filter(|neighbor| fluid.is_same(Some(*neighbor)))
+                        .map(|neighbor| neighbor.level().saturating_sub(1))
+                        .max()
+                        .unwrap_or(0)
+                };
+
+                if level != fluid.level() {
+                    change(pos, fluid.with_level(level));
+                    continue;
+                }
+            }
+
+            let can_flow_into = |pos: IVec3, level: u8| match chunks.block_at(pos) {
+                Some(BlockId::AIR) => pos.y >= 0,
+                Some(block) => blocks[block]
+                    .fluid()
+                    .is_some_and(|other| fluid.is_same(Some(other)) && other.level() < level),
+                None => false,
+            };
+
+            let below = pos - IVec3::Y;
+            if can_flow_into(below, Fluid::MAX_LEVEL - 1) {
+                change(below, fluid.with_level(Fluid::MAX_LEVEL - 1));
+                continue;
+            }
+            if level <= 1 {
+                continue;
+            }
+
+            for dir in Self::HORIZONTAL {
+                let neighbor = pos + IVec3::from(dir);
+                if can_flow_into(neighbor, level - 1) {
+                    change(neighbor, fluid.with_level(level - 1));
+                }
+            }
+        }
+
+        let mut edited = HashSet::new();
+        for (pos, block) in changes {
+            let Some(previous) = chunks.block_at(pos) else {
+                continue;
+            };
+            let Some(offset) = chunks.set_block_at(pos, block) else {
+                continue;
+            };
+
+            dirty.insert_block(pos);
+            if blocks[previous].is_opaque() != blocks[block].is_opaque()
+                || blocks[previous].light() != blocks[block].light()
+            {
+                for pos in chunks.update_light(pos, &blocks) {
+                    dirty.insert_block(pos);
+                }
+            }
+            active.insert(pos);
+            edited.insert(offset);
+        }
+
+        if edited.is_empty() {
+            return;
+        }
+
+        // Like player edits, flowed fluid has to survive regenerating the chunks it reached.
+        block_on(async {
+            db.insert_chunks(
+                edited
+                    .iter()
+                    .map(|offset| (*offset, chunks.0[offset].clone())),
+            )
+            .await;
+            db.mark_edited(edited.iter()).await;
+        });
+    }
+}
@@ -1,23 +1,75 @@
 use array_init::array_init;
-use bevy::{asset::LoadState, prelude::*};
+use bevy::{asset::LoadState, math::DVec2, prelude::*, tasks::block_on};
 use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
-use serde::Deserialize;
 use splines::{Interpolation, Key, Spline};
 
-use crate::{block::BlockId, toml_asset::TomlAsset};
+use crate::{
+    block::{BlockId, BlockRegistry},
+    toml_asset::TomlAsset,
+};
 
-use super::{Chunk, WorldPlugin, CHUNK_HEIGHT, CHUNK_WIDTH};
+use super::{
+    config::{CaveParams, WorldgenConfig, WorldgenError, WorldgenErrors},
+    db::Db,
+    feature::{Feature, PendingBlocks},
+    save::Save,
+    Chunk, WorldPlugin, CHUNK_HEIGHT, CHUNK_VOLUME, CHUNK_WIDTH,
+};
 
 #[derive(Resource, Clone, Debug)]
 pub(crate) struct Noise {
+    seed: u32,
     density: Fbm<Perlin>,
     hilliness: Fbm<Perlin>,
+    temperature: Fbm<Perlin>,
+    humidity: Fbm<Perlin>,
+    cheese: Fbm<Perlin>,
+    spaghetti: [Fbm<Perlin>; 2],
+}
+
+/// Every noise layer is seeded separately, see [`Noise::layer_seed`].
+#[derive(Clone, Copy, Debug)]
+enum NoiseLayer {
+    Density,
+    Hilliness,
+    Temperature,
+    Humidity,
+    Cheese,
+    Spaghetti1,
+    Spaghetti2,
 }
 
 #[derive(Resource, Clone, Debug)]
 pub(crate) struct WorldgenParams {
     height_bias: f64,
     hilliness: Spline<f64, f64>,
+    /// Air below this height is filled with water, from the top of every column down to its first
+    /// block. Air below a block, like closed pockets or the space under overhangs, stays dry.
+    sea_level: usize,
+    /// Climate distance over which neighboring biomes are blended.
+    biome_blend: f64,
+    biomes: Vec<Biome>,
+    caves: CaveParams,
+    pub(super) stone: BlockId,
+    pub(super) wood: BlockId,
+    pub(super) leaves: BlockId,
+    water: BlockId,
+    sand: BlockId,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Biome {
+    name: String,
+    temperature: f64,
+    humidity: f64,
+    /// Maps the hilliness to the height of the terrain.
+    height: Spline<f64, f64>,
+    surface: BlockId,
+    subsurface: BlockId,
+    /// Chance of a tree growing on a surface block.
+    trees: f64,
+    /// Chance of a boulder lying on a surface block.
+    boulders: f64,
 }
 
 #[derive(Resource, Debug)]
@@ -26,32 +78,212 @@ pub(super) struct LoadingWorldgenParams {
     is_loaded: bool,
 }
 
-#[derive(Deserialize, Debug)]
-struct Hilliness(Vec<[f64; 2]>);
-
 impl Noise {
-    fn new(density: Fbm<Perlin>, hilliness: Fbm<Perlin>) -> Self {
-        Self { density, hilliness }
+    pub(super) fn new(seed: u32) -> Self {
+        let density =
+            Fbm::<Perlin>::new(Self::layer_seed(seed, NoiseLayer::Density)).set_frequency(0.005);
+        let hilliness =
+            Fbm::<Perlin>::new(Self::layer_seed(seed, NoiseLayer::Hilliness)).set_frequency(0.0005);
+        let temperature = Fbm::<Perlin>::new(Self::layer_seed(seed, NoiseLayer::Temperature))
+            .set_frequency(0.0008);
+        let humidity =
+            Fbm::<Perlin>::new(Self::layer_seed(seed, NoiseLayer::Humidity)).set_frequency(0.0008);
+        // Cave noises are sampled at scaled positions, see [`CaveParams`].
+        let cheese = Fbm::<Perlin>::new(Self::layer_seed(seed, NoiseLayer::Cheese))
+            .set_frequency(1.0)
+            .set_octaves(3);
+        let spaghetti = [NoiseLayer::Spaghetti1, NoiseLayer::Spaghetti2].map(|layer| {
+            Fbm::<Perlin>::new(Self::layer_seed(seed, layer))
+                .set_frequency(1.0)
+                .set_octaves(2)
+        });
+        Self {
+            seed,
+            density,
+            hilliness,
+            temperature,
+            humidity,
+            cheese,
+            spaghetti,
+        }
+    }
+
+    /// Creates the noise for the seed of the world, storing the seed if the world is new.
+    pub(super) fn load(save: &Save, db: &Db) -> Self {
+        let seed = block_on(db.seed(|| save.new_seed()));
+        if save
+            .requested_seed()
+            .is_some_and(|requested| requested != seed)
+        {
+            warn!(
+                "world `{}` already exists with seed {seed}, ignoring the requested seed",
+                save.name()
+            );
+        }
+
+        Self::new(seed)
+    }
+
+    pub(crate) fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    /// Mixes the world seed with the layer using SplitMix64, so that layers are independent of
+    /// each other and of nearby world seeds.
+    fn layer_seed(seed: u32, layer: NoiseLayer) -> u32 {
+        split_mix((seed as u64) << 32 | layer as u64) as u32
+    }
+
+    /// Random value of a column, used to place features.
+    fn column_hash(&self, x: i32, z: i32) -> u64 {
+        split_mix(split_mix((self.seed as u64) << 32 | x as u32 as u64) ^ z as u32 as u64)
     }
 
     pub(crate) fn hilliness(&self) -> &Fbm<Perlin> {
         &self.hilliness
     }
+
+    /// Temperature and humidity at `pos`, both in `0.0..=1.0`.
+    pub(crate) fn climate(&self, pos: DVec2) -> DVec2 {
+        let pos = pos.to_array();
+        let climate = DVec2::new(self.temperature.get(pos), self.humidity.get(pos));
+        ((climate + 1.0) / 2.0).clamp(DVec2::ZERO, DVec2::ONE)
+    }
 }
 
-impl Default for Noise {
-    fn default() -> Self {
-        let density = Fbm::<Perlin>::new(0).set_frequency(0.005);
-        let hilliness = Fbm::<Perlin>::new(0).set_frequency(0.0005);
-        Self::new(density, hilliness)
+impl FromWorld for Noise {
+    fn from_world(world: &mut World) -> Self {
+        Self::load(world.resource::<Save>(), world.resource::<Db>())
     }
 }
 
 impl WorldgenParams {
-    pub(super) fn new(height_bias: f64, hilliness: Spline<f64, f64>) -> Self {
+    /// Builds the params from a config validated by [`WorldgenConfig::from_toml`].
+    fn new(config: WorldgenConfig, blocks: &BlockRegistry) -> Self {
+        let WorldgenConfig {
+            height_bias: Some(height_bias),
+            hilliness: Some(hilliness),
+            sea_level: Some(sea_level),
+            biome_blend: Some(biome_blend),
+            biomes: Some(biomes),
+            caves: Some(caves),
+        } = config
+        else {
+            unreachable!("missing keys are reported by validation");
+        };
+        let block = |name: &str| blocks.id(name).unwrap();
+
+        let biomes = biomes
+            .into_iter()
+            .map(|biome| Biome {
+                height: spline(&biome.height),
+                temperature: biome.temperature,
+                humidity: biome.humidity,
+                surface: block(&biome.surface),
+                subsurface: block(&biome.subsurface),
+                trees: biome.trees,
+                boulders: biome.boulders,
+                name: biome.name,
+            })
+            .collect();
+
         Self {
             height_bias,
-            hilliness,
+            hilliness: spline(&hilliness),
+            sea_level: sea_level as usize,
+            biome_blend,
+            biomes,
+            caves,
+            stone: block("stone"),
+            wood: block("wood"),
+            leaves: block("leaves"),
+            water: block("water"),
+            sand: block("sand"),
+        }
+    }
+
+    pub(super) fn from_toml(
+        table: &TomlAsset,
+        blocks: &BlockRegistry,
+    ) -> Result<Self, WorldgenErrors> {
+        WorldgenConfig::from_toml(table, blocks).map(|config| Self::new(config, blocks))
+    }
+
+    /// Returns the biome at `pos` and the height of the terrain there, as a fraction of the
+    /// height above [`Chunk::MIN_HEIGHT`]. Near biome borders the heights of all biomes whose
+    /// climate is within `biome_blend` of the closest one are blended.
+    fn sample(&self, noise: &Noise, pos: DVec2) -> (&Biome, f64) {
+        let hilliness = self
+            .hilliness
+            .clamped_sample((noise.hilliness.get(pos.to_array()) + 1.0) / 2.0)
+            .unwrap();
+
+        let climate = noise.climate(pos);
+        let distances = self
+            .biomes
+            .iter()
+            .map(|biome| climate.distance(biome.climate()));
+        let (closest, min_distance) = distances
+            .clone()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .unwrap();
+
+        let mut height = 0.0;
+        let mut total_weight = 0.0;
+        for (biome, distance) in self.biomes.iter().zip(distances) {
+            let weight = 1.0 - (distance - min_distance) / self.biome_blend;
+            if weight > 0.0 {
+                height += biome.height.clamped_sample(hilliness).unwrap() * weight;
+                total_weight += weight;
+            }
+        }
+
+        (&self.biomes[closest], height / total_weight)
+    }
+
+    pub(crate) fn biome(&self, noise: &Noise, pos: DVec2) -> &Biome {
+        self.sample(noise, pos).0
+    }
+}
+
+impl CaveParams {
+    fn is_cave(&self, noise: &Noise, pos: IVec3) -> bool {
+        if pos.y < self.min_height as i32 {
+            return false;
+        }
+
+        let cheese_pos = (pos.as_dvec3() * self.cheese_frequency).to_array();
+        if noise.cheese.get(cheese_pos) > self.cheese_threshold {
+            return true;
+        }
+
+        let spaghetti_pos = (pos.as_dvec3() * self.spaghetti_frequency).to_array();
+        noise
+            .spaghetti
+            .iter()
+            .all(|noise| noise.get(spaghetti_pos).abs() < self.spaghetti_width)
+    }
+}
+
+impl Biome {
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn climate(&self) -> DVec2 {
+        DVec2::new(self.temperature, self.humidity)
+    }
+
+    /// Picks the feature rooted on a surface block of the biome.
+    fn feature(&self, hash: u64) -> Option<Feature> {
+        let roll = (hash >> 11) as f64 / (1u64 << 53) as f64;
+        if roll < self.trees {
+            Some(Feature::Tree)
+        } else if roll < self.trees + self.boulders {
+            Some(Feature::Boulder)
+        } else {
+            None
         }
     }
 }
@@ -71,10 +303,22 @@ impl Chunk {
     const MIN_HEIGHT: usize = 32;
     const MIN_GRASS_LAYERS: i32 = 3;
     const MAX_GRASS_LAYERS: i32 = 6;
+    /// Surface layers at most this high above the sea level are sand.
+    const SHORE_HEIGHT: usize = 1;
 
-    pub(super) fn generate(offset: IVec2, noise: &Noise, params: &WorldgenParams) -> Self {
-        let mut hilliness_cache = vec![f64::MAX; CHUNK_HEIGHT * CHUNK_HEIGHT];
-        let mut chunk = array_init(|i| {
+    pub(super) fn generate(
+        offset: IVec2,
+        noise: &Noise,
+        params: &WorldgenParams,
+        blocks: &BlockRegistry,
+    ) -> (Self, PendingBlocks) {
+        let columns: [(&Biome, f64); CHUNK_WIDTH * CHUNK_WIDTH] = array_init(|i| {
+            let x = (i % CHUNK_WIDTH) as i32 + offset.x * CHUNK_WIDTH as i32;
+            let z = (i / CHUNK_WIDTH) as i32 + offset.y * CHUNK_WIDTH as i32;
+            params.sample(noise, DVec2::new(x as f64, z as f64))
+        });
+
+        let mut chunk: [BlockId; CHUNK_VOLUME] = array_init(|i| {
             let x = i % CHUNK_WIDTH;
             let z = (i / CHUNK_WIDTH) % CHUNK_WIDTH;
             let y = (i / CHUNK_WIDTH / CHUNK_WIDTH) % CHUNK_HEIGHT;
@@ -85,31 +329,29 @@ impl Chunk {
                 z as i32 + offset.y * CHUNK_WIDTH as i32,
             );
 
-            let mut hilliness = hilliness_cache[x + z * CHUNK_WIDTH];
-            if hilliness == f64::MAX {
-                hilliness = params
-                    .hilliness
-                    .clamped_sample((noise.hilliness.get([pos.x as f64, pos.z as f64]) + 1.0) / 2.0)
-                    .unwrap();
-                hilliness_cache[x + z * CHUNK_WIDTH] = hilliness;
-            }
-
-            let height_offset = hilliness * (CHUNK_HEIGHT - Self::MIN_HEIGHT) as f64;
+            let (_, height) = columns[x + z * CHUNK_WIDTH];
+            let height_offset = height * (CHUNK_HEIGHT - Self::MIN_HEIGHT) as f64;
             let elevation = pos.y as f64 / (Self::MIN_HEIGHT as f64 + height_offset);
 
             let mut density = noise.density.get(pos.as_dvec3().to_array());
             density -= ((elevation - 0.5) * params.height_bias).tanh();
 
             if density > 0.0 {
-                BlockId::Stone
+                params.stone
             } else {
-                BlockId::Air
+                BlockId::AIR
             }
         });
 
+        let carved = Self::carve_caves(&mut chunk, offset, noise, params);
+        let mut features = Vec::new();
+
         for x in 0..CHUNK_WIDTH {
             for z in 0..CHUNK_WIDTH {
+                let (biome, _) = columns[x + z * CHUNK_WIDTH];
                 let mut layer = Self::MAX_GRASS_LAYERS;
+                let mut is_covered = false;
+                let mut is_cave = false;
                 for y in (0..CHUNK_HEIGHT).rev() {
                     let elevation = y as f64 / CHUNK_HEIGHT as f64;
                     let max_layers = (((1.0 - elevation) * Self::MAX_GRASS_LAYERS as f64).round()
@@ -119,77 +361,278 @@ impl Chunk {
 
                     let i = x + (y * CHUNK_WIDTH * CHUNK_WIDTH) + z * CHUNK_WIDTH;
                     let block = chunk[i];
-                    match block {
-                        BlockId::Air => layer += 1,
-                        BlockId::Stone => {
-                            if layer > 0 {
-                                chunk[i] = if chunk
+                    if block == BlockId::AIR {
+                        // Caves below the surface don't get surface layers of their own.
+                        is_cave = is_covered && carved[i];
+                        if !is_cave {
+                            layer += 1;
+                        }
+                        if !is_covered && y < params.sea_level {
+                            chunk[i] = params.water;
+                        }
+                        continue;
+                    }
+
+                    if block == params.stone {
+                        if layer > 0 {
+                            chunk[i] = if y <= params.sea_level + Self::SHORE_HEIGHT {
+                                params.sand
+                            } else if is_cave
+                                || chunk
                                     .get(i + CHUNK_WIDTH * CHUNK_WIDTH)
-                                    .is_some_and(|block| block.is_opaque())
-                                {
-                                    BlockId::Dirt
-                                } else {
-                                    BlockId::Grass
-                                };
-                            }
-                            layer -= 1
+                                    .is_some_and(|&block| blocks[block].is_opaque())
+                            {
+                                biome.subsurface
+                            } else {
+                                biome.surface
+                            };
                         }
-                        _ => unreachable!(),
-                    };
+                        layer -= 1;
+                    }
+
+                    if !is_covered
+                        && chunk[i] == biome.surface
+                        && (params.sea_level..CHUNK_HEIGHT).contains(&(y + 1))
+                    {
+                        let hash = noise.column_hash(
+                            x as i32 + offset.x * CHUNK_WIDTH as i32,
+                            z as i32 + offset.y * CHUNK_WIDTH as i32,
+                        );
+                        if let Some(feature) = biome.feature(hash) {
+                            let root = IVec3::new(x as i32, y as i32 + 1, z as i32);
+                            features.push((root, feature, hash));
+                        }
+                    }
+                    is_covered = true;
+                    is_cave = false;
                 }
             }
         }
 
-        Self(chunk)
+        let pending = Self::place_features(&mut chunk, offset, &features, params);
+
+        (Self::from_blocks(&chunk), pending)
+    }
+
+    /// Replaces stone inside caves with air. Returns which blocks were carved.
+    fn carve_caves(
+        chunk: &mut [BlockId; CHUNK_VOLUME],
+        offset: IVec2,
+        noise: &Noise,
+        params: &WorldgenParams,
+    ) -> Vec<bool> {
+        let mut carved = vec![false; CHUNK_VOLUME];
+        for (i, block) in chunk.iter_mut().enumerate() {
+            if *block != params.stone {
+                continue;
+            }
+
+            let pos = IVec3::new(
+                (i % CHUNK_WIDTH) as i32 + offset.x * CHUNK_WIDTH as i32,
+                (i / CHUNK_WIDTH / CHUNK_WIDTH) as i32,
+                ((i / CHUNK_WIDTH) % CHUNK_WIDTH) as i32 + offset.y * CHUNK_WIDTH as i32,
+            );
+            if params.caves.is_cave(noise, pos) {
+                *block = BlockId::AIR;
+                carved[i] = true;
+            }
+        }
+        carved
     }
 }
 
 impl WorldPlugin {
+    /// Creates the params once `worldgen.toml` is loaded, and again whenever it changes while it
+    /// is invalid.
     pub(super) fn create_worldgen_params(
         mut commands: Commands,
+        mut events: EventReader<AssetEvent<TomlAsset>>,
         asset_server: Res<AssetServer>,
         mut loading_params: ResMut<LoadingWorldgenParams>,
-        mut toml_assets: ResMut<Assets<TomlAsset>>,
+        toml_assets: Res<Assets<TomlAsset>>,
+        blocks: Res<BlockRegistry>,
     ) {
-        if loading_params.is_loaded
-            || asset_server.load_state(loading_params.handle.id()) != LoadState::Loaded
+        let handle = loading_params.handle.clone();
+        if events
+            .read()
+            .any(|ev| ev.is_loaded_with_dependencies(&handle) || ev.is_modified(&handle))
         {
+            loading_params.is_loaded = false;
+        }
+        if loading_params.is_loaded {
             return;
         }
+
+        let params = match asset_server.load_state(&handle) {
+            LoadState::Loaded => {
+                WorldgenParams::from_toml(toml_assets.get(&handle).unwrap(), &blocks)
+            }
+            LoadState::Failed(err) => {
+                Err(WorldgenErrors(vec![WorldgenError::Load(err.to_string())]))
+            }
+            _ => return,
+        };
         loading_params.is_loaded = true;
 
-        let table = toml_assets.get_mut(&loading_params.handle).unwrap();
-
-        let height_bias = table
-            .0
-            .get("height_bias")
-            .expect("`height_bias` is required")
-            .as_float()
-            .filter(|v| *v > 0.0)
-            .expect("invalid value for `height_bias`");
-
-        let hilliness = Hilliness::deserialize(
-            table
-                .0
-                .get("hilliness")
-                .expect("`hilliness` is required")
-                .clone(),
-        )
-        .expect("invalid value for `hilliness`");
-
-        for &[x, y] in &hilliness.0 {
-            if !(0.0..=1.0).contains(&x) || !(0.0..=1.0).contains(&y) {
-                panic!("invalid value for `hilliness`");
+        match params {
+            Ok(params) => {
+                commands.insert_resource(params);
+                commands.remove_resource::<WorldgenErrors>();
+            }
+            Err(errors) => {
+                error!("invalid `worldgen.toml`:\n{errors}");
+                commands.insert_resource(errors);
             }
         }
+    }
+
+    /// Rebuilds the params when `worldgen.toml` changes, keeping the previous ones if it is
+    /// invalid.
+    pub(super) fn reload_worldgen_params(
+        mut events: EventReader<AssetEvent<TomlAsset>>,
+        loading_params: Res<LoadingWorldgenParams>,
+        toml_assets: Res<Assets<TomlAsset>>,
+        blocks: Res<BlockRegistry>,
+        mut params: ResMut<WorldgenParams>,
+    ) {
+        for ev in events.read() {
+            if !ev.is_modified(&loading_params.handle) {
+                continue;
+            }
 
-        let hilliness = Spline::from_iter(
-            hilliness
-                .0
+            let table = toml_assets.get(&loading_params.handle).unwrap();
+            match WorldgenParams::from_toml(table, &blocks) {
+                Ok(new_params) => {
+                    *params = new_params;
+                    info!("reloaded `worldgen.toml`");
+                }
+                Err(errors) => {
+                    error!("invalid `worldgen.toml`, keeping the previous version:\n{errors}")
+                }
+            }
+        }
+    }
+}
+
+/// SplitMix64 finalizer.
+fn split_mix(z: u64) -> u64 {
+    let mut z = z.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+fn spline(points: &[[f64; 2]]) -> Spline<f64, f64> {
+    Spline::from_iter(
+        points
+            .iter()
+            .map(|&[x, y]| Key::new(x, y, Interpolation::Cosine)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use bevy::utils::HashSet;
+
+    use super::*;
+
+    const LAYERS: [NoiseLayer; 7] = [
+        NoiseLayer::Density,
+        NoiseLayer::Hilliness,
+        NoiseLayer::Temperature,
+        NoiseLayer::Humidity,
+        NoiseLayer::Cheese,
+        NoiseLayer::Spaghetti1,
+        NoiseLayer::Spaghetti2,
+    ];
+
+    fn params(blocks: &BlockRegistry) -> WorldgenParams {
+        let table = TomlAsset::read(Path::new("assets/worldgen.toml")).unwrap();
+        WorldgenParams::from_toml(&table, blocks).unwrap()
+    }
+
+    #[test]
+    fn same_seed_generates_same_chunks() {
+        let blocks = BlockRegistry::from_assets();
+        let params = params(&blocks);
+
+        for offset in [IVec2::ZERO, IVec2::new(-3, 7), IVec2::new(12, -5)] {
+            let (a, pending_a) = Chunk::generate(offset, &Noise::new(42), &params, &blocks);
+            let (b, pending_b) = Chunk::generate(offset, &Noise::new(42), &params, &blocks);
+            assert_eq!(a.to_bytes(), b.to_bytes());
+            assert_eq!(pending_a, pending_b);
+
+            let (other, _) = Chunk::generate(offset, &Noise::new(43), &params, &blocks);
+            assert_ne!(a.to_bytes(), other.to_bytes());
+        }
+    }
+
+    #[test]
+    fn layers_get_different_noise() {
+        for seed in [0, 1, 42, u32::MAX] {
+            let seeds: HashSet<_> = LAYERS
                 .iter()
-                .map(|&[x, y]| Key::new(x, y, Interpolation::Cosine)),
-        );
+                .map(|&layer| Noise::layer_seed(seed, layer))
+                .collect();
+            assert_eq!(seeds.len(), LAYERS.len());
+
+            for layer in LAYERS {
+                assert_ne!(
+                    Noise::layer_seed(seed, layer),
+                    Noise::layer_seed(seed.wrapping_add(1), layer)
+                );
+            }
+
+            let noise = Noise::new(seed);
+            let points: Vec<_> = (0..16)
+                .map(|i| [i as f64 * 100.0, -i as f64 * 50.0])
+                .collect();
+            let sample = |fbm: &Fbm<Perlin>| points.iter().map(|&p| fbm.get(p)).collect::<Vec<_>>();
+            assert_ne!(sample(&noise.temperature), sample(&noise.humidity));
+            assert_ne!(sample(&noise.hilliness), sample(&noise.temperature));
+        }
+    }
+
+    #[test]
+    fn sea_only_fills_air_open_from_above() {
+        let blocks = BlockRegistry::from_assets();
+        let mut params = params(&blocks);
+        // Features can hang over the sea, which would hide what is tested. A weak height bias
+        // and a high sea level make pockets of air below the sea common.
+        for biome in &mut params.biomes {
+            biome.trees = 0.0;
+            biome.boulders = 0.0;
+        }
+        params.height_bias = 0.2;
+        params.sea_level = 200;
+        let noise = Noise::new(0);
+        let mut dry_pockets = 0;
 
-        commands.insert_resource(WorldgenParams::new(height_bias, hilliness));
+        for x in -2..2 {
+            for z in -2..2 {
+                let (chunk, _) = Chunk::generate(IVec2::new(x, z), &noise, &params, &blocks);
+                for column in 0..CHUNK_WIDTH * CHUNK_WIDTH {
+                    let column = IVec3::new(
+                        (column % CHUNK_WIDTH) as i32,
+                        0,
+                        (column / CHUNK_WIDTH) as i32,
+                    );
+                    let first_block = (0..CHUNK_HEIGHT as i32)
+                        .rev()
+                        .map(|y| column + IVec3::Y * y)
+                        .find(|&pos| ![BlockId::AIR, params.water].contains(&chunk.get(pos)));
+                    if let Some(first_block) = first_block {
+                        for y in 0..first_block.y {
+                            let block = chunk.get(column + IVec3::Y * y);
+                            assert_ne!(block, params.water);
+                            dry_pockets += (block == BlockId::AIR) as usize;
+                        }
+                    }
+                }
+            }
+        }
+        assert!(dry_pockets > 0);
     }
 }
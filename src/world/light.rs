@@ -0,0 +1,351 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use bevy::{prelude::*, utils::HashSet};
+use strum::IntoEnumIterator;
+
+use crate::{
+    block::{Block, BlockRegistry},
+    direction::Direction,
+};
+
+use super::{world_to_local, Chunk, Chunks, CHUNK_HEIGHT, CHUNK_VOLUME, CHUNK_WIDTH};
+
+pub(super) const MAX_LIGHT: u8 = 15;
+
+/// Sky and block light of every block in a chunk, packed as `sky << 4 | block`.
+#[derive(Clone, Debug)]
+pub(super) struct LightMap(Box<[u8]>);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(super) enum LightKind {
+    /// Light flooded down from the top of each column.
+    Sky,
+    /// Light emitted by blocks.
+    Block,
+}
+
+/// Blocks and light that the propagation algorithms operate on.
+trait LightVolume {
+    /// Returns `None` for positions outside of the volume.
+    fn light(&self, pos: IVec3, kind: LightKind) -> Option<u8>;
+    fn set_light(&mut self, pos: IVec3, kind: LightKind, level: u8);
+    fn is_opaque(&self, pos: IVec3) -> bool;
+    fn emission(&self, pos: IVec3) -> u8;
+}
+
+/// A single chunk, in local coordinates.
+struct ChunkVolume<'a> {
+    chunk: &'a mut Chunk,
+    blocks: &'a BlockRegistry,
+}
+
+/// All loaded chunks, in world coordinates.
+struct WorldVolume<'a> {
+    chunks: &'a mut Chunks,
+    blocks: &'a BlockRegistry,
+    changed: HashSet<IVec3>,
+}
+
+impl LightMap {
+    /// Packed light of a block above the world.
+    pub(super) const SKY: u8 = MAX_LIGHT << 4;
+
+    pub(super) fn get(&self, pos: IVec3) -> u8 {
+        self.0[Self::index(pos)]
+    }
+
+    fn level(&self, pos: IVec3, kind: LightKind) -> u8 {
+        let light = self.get(pos);
+        match kind {
+            LightKind::Sky => light >> 4,
+            LightKind::Block => light & MAX_LIGHT,
+        }
+    }
+
+    fn set_level(&mut self, pos: IVec3, kind: LightKind, level: u8) {
+        let light = &mut self.0[Self::index(pos)];
+        *light = match kind {
+            LightKind::Sky => (*light & MAX_LIGHT) | (level << 4),
+            LightKind::Block => (*light & !MAX_LIGHT) | level,
+        };
+    }
+
+    fn index(pos: IVec3) -> usize {
+        pos.x as usize + pos.z as usize * CHUNK_WIDTH + pos.y as usize * CHUNK_WIDTH * CHUNK_WIDTH
+    }
+}
+
+impl Default for LightMap {
+    fn default() -> Self {
+        Self(vec![0; CHUNK_VOLUME].into_boxed_slice())
+    }
+}
+
+impl LightVolume for ChunkVolume<'_> {
+    fn light(&self, pos: IVec3, kind: LightKind) -> Option<u8> {
+        if pos.x < 0 || pos.z < 0 || pos.x >= CHUNK_WIDTH as i32 || pos.z >= CHUNK_WIDTH as i32 {
+            return None;
+        }
+        sky_or(pos, kind, || self.chunk.light.level(pos, kind))
+    }
+
+    fn set_light(&mut self, pos: IVec3, kind: LightKind, level: u8) {
+        self.chunk.light.set_level(pos, kind, level);
+    }
+
+    fn is_opaque(&self, pos: IVec3) -> bool {
+        self.blocks[self.chunk.get(pos)].is_opaque()
+    }
+
+    fn emission(&self, pos: IVec3) -> u8 {
+        self.blocks[self.chunk.get(pos)].light()
+    }
+}
+
+impl LightVolume for WorldVolume<'_> {
+    fn light(&self, pos: IVec3, kind: LightKind) -> Option<u8> {
+        let (offset, local_pos) = world_to_local(pos);
+        let chunk = self.chunks.0.get(&offset)?;
+        sky_or(pos, kind, || chunk.light.level(local_pos, kind))
+    }
+
+    fn set_light(&mut self, pos: IVec3, kind: LightKind, level: u8) {
+        if pos.y < 0 || pos.y >= CHUNK_HEIGHT as i32 {
+            return;
+        }
+
+        let (offset, local_pos) = world_to_local(pos);
+        if let Some(chunk) = self.chunks.0.get_mut(&offset) {
+            Arc::make_mut(chunk).light.set_level(local_pos, kind, level);
+            self.changed.insert(pos);
+        }
+    }
+
+    fn is_opaque(&self, pos: IVec3) -> bool {
+        self.block(pos).is_some_and(|block| block.is_opaque())
+    }
+
+    fn emission(&self, pos: IVec3) -> u8 {
+        self.block(pos).map_or(0, |block| block.light())
+    }
+}
+
+impl WorldVolume<'_> {
+    fn block(&self, pos: IVec3) -> Option<&Block> {
+        if pos.y < 0 || pos.y >= CHUNK_HEIGHT as i32 {
+            return None;
+        }
+
+        let (offset, local_pos) = world_to_local(pos);
+        let chunk = self.chunks.0.get(&offset)?;
+        Some(&self.blocks[chunk.get(local_pos)])
+    }
+}
+
+impl Chunk {
+    /// Computes the light of the chunk without taking its neighbors into account.
+    pub(super) fn compute_light(&mut self, blocks: &BlockRegistry) {
+        self.light = LightMap::default();
+
+        let mut volume = ChunkVolume {
+            chunk: self,
+            blocks,
+        };
+
+        let mut sky = VecDeque::new();
+        let mut block = VecDeque::new();
+
+        for x in 0..CHUNK_WIDTH as i32 {
+            for z in 0..CHUNK_WIDTH as i32 {
+                let mut is_exposed = true;
+                for y in (0..CHUNK_HEIGHT as i32).rev() {
+                    let pos = IVec3::new(x, y, z);
+                    is_exposed &= !volume.is_opaque(pos);
+                    if is_exposed {
+                        volume.set_light(pos, LightKind::Sky, MAX_LIGHT);
+                        sky.push_back(pos);
+                    }
+
+                    let emission = volume.emission(pos);
+                    if emission > 0 {
+                        volume.set_light(pos, LightKind::Block, emission);
+                        block.push_back(pos);
+                    }
+                }
+            }
+        }
+
+        propagate(&mut volume, LightKind::Sky, sky);
+        propagate(&mut volume, LightKind::Block, block);
+    }
+}
+
+impl Chunks {
+    /// Inserts a chunk whose own light has already been computed and spreads light across its
+    /// borders with the loaded neighbors. Returns the positions whose light changed.
+    pub(super) fn insert(
+        &mut self,
+        offset: IVec2,
+        chunk: Arc<Chunk>,
+        blocks: &BlockRegistry,
+    ) -> HashSet<IVec3> {
+        self.0.insert(offset, chunk);
+
+        let mut volume = WorldVolume {
+            chunks: self,
+            blocks,
+            changed: HashSet::new(),
+        };
+        let origin = IVec3::new(offset.x, 0, offset.y) * CHUNK_WIDTH as i32;
+        let max = CHUNK_WIDTH as i32 - 1;
+
+        for kind in [LightKind::Sky, LightKind::Block] {
+            let mut queue = VecDeque::new();
+
+            for dir in [
+                Direction::North,
+                Direction::South,
+                Direction::West,
+                Direction::East,
+            ] {
+                if !volume.chunks.0.contains_key(&(offset + IVec2::from(dir))) {
+                    continue;
+                }
+
+                for i in 0..CHUNK_WIDTH as i32 {
+                    for y in 0..CHUNK_HEIGHT as i32 {
+                        let edge = match dir {
+                            Direction::North => IVec3::new(i, y, max),
+                            Direction::South => IVec3::new(i, y, 0),
+                            Direction::West => IVec3::new(max, y, i),
+                            _ => IVec3::new(0, y, i),
+                        };
+                        let pos = origin + edge;
+                        let neighbor = pos + IVec3::from(dir);
+
+                        let level = volume.light(pos, kind).unwrap();
+                        let neighbor_level = volume.light(neighbor, kind).unwrap();
+                        if level > neighbor_level + 1 {
+                            queue.push_back(pos);
+                        } else if neighbor_level > level + 1 {
+                            queue.push_back(neighbor);
+                        }
+                    }
+                }
+            }
+
+            propagate(&mut volume, kind, queue);
+        }
+
+        volume.changed
+    }
+
+    /// Updates the light around a block that has just been changed. Returns the positions whose
+    /// light changed.
+    pub(super) fn update_light(&mut self, pos: IVec3, blocks: &BlockRegistry) -> HashSet<IVec3> {
+        let mut volume = WorldVolume {
+            chunks: self,
+            blocks,
+            changed: HashSet::new(),
+        };
+
+        for kind in [LightKind::Sky, LightKind::Block] {
+            let mut queue = remove(&mut volume, kind, pos);
+
+            if kind == LightKind::Block {
+                let emission = volume.emission(pos);
+                if emission > 0 {
+                    volume.set_light(pos, kind, emission);
+                    queue.push_back(pos);
+                }
+            }
+
+            if !volume.is_opaque(pos) {
+                queue.extend(Direction::iter().map(|dir| pos + IVec3::from(dir)));
+            }
+
+            propagate(&mut volume, kind, queue);
+        }
+
+        volume.changed
+    }
+}
+
+/// Spreads light outwards from the queued positions.
+fn propagate(volume: &mut impl LightVolume, kind: LightKind, mut queue: VecDeque<IVec3>) {
+    while let Some(pos) = queue.pop_front() {
+        let Some(level) = volume.light(pos, kind).filter(|&level| level > 1) else {
+            continue;
+        };
+
+        for dir in Direction::iter() {
+            let neighbor = pos + IVec3::from(dir);
+            let Some(neighbor_level) = volume.light(neighbor, kind) else {
+                continue;
+            };
+
+            let level = spread(kind, dir, level);
+            if level > neighbor_level && !volume.is_opaque(neighbor) {
+                volume.set_light(neighbor, kind, level);
+                queue.push_back(neighbor);
+            }
+        }
+    }
+}
+
+/// Removes the light that originated from `pos`. Returns the positions that have to be
+/// propagated again to fill the darkened area.
+fn remove(volume: &mut impl LightVolume, kind: LightKind, pos: IVec3) -> VecDeque<IVec3> {
+    let mut relight = VecDeque::new();
+    let mut queue = VecDeque::new();
+
+    if let Some(level) = volume.light(pos, kind).filter(|&level| level > 0) {
+        volume.set_light(pos, kind, 0);
+        queue.push_back((pos, level));
+    }
+
+    while let Some((pos, level)) = queue.pop_front() {
+        for dir in Direction::iter() {
+            let neighbor = pos + IVec3::from(dir);
+            let Some(neighbor_level) = volume.light(neighbor, kind).filter(|&level| level > 0)
+            else {
+                continue;
+            };
+
+            if neighbor_level < level || spread(kind, dir, level) == neighbor_level {
+                volume.set_light(neighbor, kind, 0);
+                queue.push_back((neighbor, neighbor_level));
+
+                let emission = volume.emission(neighbor);
+                if kind == LightKind::Block && emission > 0 {
+                    volume.set_light(neighbor, kind, emission);
+                    relight.push_back(neighbor);
+                }
+            } else {
+                relight.push_back(neighbor);
+            }
+        }
+    }
+
+    relight
+}
+
+/// Light level of a block next to one with `level` light, in direction `dir`.
+fn spread(kind: LightKind, dir: Direction, level: u8) -> u8 {
+    if kind == LightKind::Sky && dir == Direction::Down && level == MAX_LIGHT {
+        MAX_LIGHT
+    } else {
+        level - 1
+    }
+}
+
+/// Light above the world is full sky light, and there is no light below it.
+fn sky_or(pos: IVec3, kind: LightKind, light: impl FnOnce() -> u8) -> Option<u8> {
+    if pos.y < 0 {
+        None
+    } else if pos.y >= CHUNK_HEIGHT as i32 {
+        Some(if kind == LightKind::Sky { MAX_LIGHT } else { 0 })
+    } else {
+        Some(light())
+    }
+}
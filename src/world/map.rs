@@ -0,0 +1,150 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, bail, Context};
+use bevy::prelude::*;
+use image::{GrayImage, Luma, Rgb, RgbImage};
+use noise::NoiseFn;
+
+use crate::{
+    block::{BlockId, BlockRegistry},
+    direction::Direction,
+    textures::TexturesPlugin,
+    toml_asset::TomlAsset,
+};
+
+use super::{
+    save::fnv1a, spawn::generate_chunks, ChunkArea, Noise, WorldgenParams, CHUNK_HEIGHT,
+    CHUNK_WIDTH,
+};
+
+/// Generates the chunks of `area` and writes top-down maps of them into `out`, one pixel per
+/// column: the height of the ground, the color of the top block, the hilliness noise and the
+/// biome. Biomes get the same color in every map, so maps of different params can be compared.
+pub(crate) fn export_maps(
+    seed: u32,
+    area: ChunkArea,
+    worldgen: &Path,
+    out: &Path,
+) -> anyhow::Result<()> {
+    let assets = Path::new("assets");
+    let blocks = BlockRegistry::from_toml(&TomlAsset::read(&assets.join("blocks.toml"))?)
+        .map_err(|errors| anyhow!("invalid `blocks.toml`:\n{errors}"))?;
+    let params = WorldgenParams::from_toml(&TomlAsset::read(worldgen)?, &blocks)
+        .map_err(|errors| anyhow!("invalid `{}`:\n{errors}", worldgen.display()))?;
+    let colors = block_colors(&blocks, &assets.join("textures/blocks.png"))?;
+    let noise = Noise::new(seed);
+
+    let offsets = area.offsets();
+    let (Some(min), Some(max)) = (
+        offsets.iter().copied().reduce(IVec2::min),
+        offsets.iter().copied().reduce(IVec2::max),
+    ) else {
+        bail!("no chunks in {area:?}");
+    };
+    let size = ((max - min + 1) * CHUNK_WIDTH as i32).as_uvec2();
+    let origin = min * CHUNK_WIDTH as i32;
+
+    let mut height = GrayImage::new(size.x, size.y);
+    let mut top = RgbImage::new(size.x, size.y);
+    let mut hilliness = GrayImage::new(size.x, size.y);
+    let mut biome = RgbImage::new(size.x, size.y);
+
+    let (chunks, _) = generate_chunks(&offsets, &noise, &params, &blocks);
+    for (offset, chunk) in &chunks {
+        for x in 0..CHUNK_WIDTH as i32 {
+            for z in 0..CHUNK_WIDTH as i32 {
+                let pos = *offset * CHUNK_WIDTH as i32 + IVec2::new(x, z);
+                let pixel = (pos - origin).as_uvec2();
+                let column = (0..CHUNK_HEIGHT as i32)
+                    .rev()
+                    .map(|y| (y, chunk.get(IVec3::new(x, y, z))))
+                    .filter(|(_, block)| *block != BlockId::AIR);
+
+                if let Some((_, block)) = column.clone().next() {
+                    top.put_pixel(pixel.x, pixel.y, colors[u8::from(block) as usize]);
+                }
+
+                // Fluids are skipped, so that the terrain below the sea is visible.
+                let ground = column.clone().find(|(_, block)| !blocks[*block].is_fluid());
+                if let Some((y, _)) = ground {
+                    height.put_pixel(pixel.x, pixel.y, Luma([y as u8]));
+                }
+
+                let noise_pos = pos.as_dvec2();
+                let value = (noise.hilliness().get(noise_pos.to_array()) + 1.0) / 2.0;
+                hilliness.put_pixel(pixel.x, pixel.y, Luma([(value * 255.0) as u8]));
+
+                let name = params.biome(&noise, noise_pos).name();
+                biome.put_pixel(pixel.x, pixel.y, biome_color(name));
+            }
+        }
+    }
+
+    fs::create_dir_all(out)?;
+    height.save(out.join("height.png"))?;
+    top.save(out.join("top.png"))?;
+    hilliness.save(out.join("hilliness.png"))?;
+    biome.save(out.join("biome.png"))?;
+    Ok(())
+}
+
+/// Average color of the top texture of every block, ignoring fully transparent texels.
+fn block_colors(blocks: &BlockRegistry, texture: &Path) -> anyhow::Result<Vec<Rgb<u8>>> {
+    let image = image::open(texture)
+        .with_context(|| format!("failed to open `{}`", texture.display()))?
+        .into_rgba8();
+    let tile_size = TexturesPlugin::BLOCKS_TEXTURE_TILE_SIZE as u32;
+    let columns = image.width() / tile_size;
+
+    Ok(blocks
+        .texture_layers()
+        .chunks(6)
+        .map(|faces| {
+            let layer = faces[Direction::Up as usize];
+            if layer == u32::MAX {
+                return Rgb([0, 0, 0]);
+            }
+
+            let corner = UVec2::new(layer % columns, layer / columns) * tile_size;
+            let mut sum = UVec3::ZERO;
+            let mut count = 0;
+            for x in 0..tile_size {
+                for y in 0..tile_size {
+                    let [r, g, b, a] = image.get_pixel(corner.x + x, corner.y + y).0;
+                    if a > 0 {
+                        sum += UVec3::new(r as u32, g as u32, b as u32);
+                        count += 1;
+                    }
+                }
+            }
+
+            let color = sum / count.max(1);
+            Rgb([color.x as u8, color.y as u8, color.z as u8])
+        })
+        .collect())
+}
+
+/// Color derived from the name of the biome, FNV-1a hashed.
+fn biome_color(name: &str) -> Rgb<u8> {
+    let [r, g, b, _] = fnv1a(name.as_bytes()).to_le_bytes();
+    Rgb([r / 2 + 64, g / 2 + 64, b / 2 + 64])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_area_is_an_error() {
+        let out = std::env::temp_dir().join(format!("vxl-map-test-{}", std::process::id()));
+        let err = export_maps(
+            0,
+            ChunkArea::Rect(IVec2::ONE, IVec2::ZERO),
+            Path::new("assets/worldgen.toml"),
+            &out,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("no chunks"));
+        assert!(!out.exists());
+    }
+}
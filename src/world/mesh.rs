@@ -2,95 +2,563 @@ use bevy::{
     prelude::*,
     render::{
         mesh::{Indices, PrimitiveTopology},
+        primitives::Aabb,
         render_asset::RenderAssetUsages,
     },
     tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
     utils::HashMap,
 };
-use strum::IntoEnumIterator;
+use strum::{EnumCount, EnumIter, IntoEnumIterator};
 
-use crate::{direction::Direction, materials::ATTRIBUTE_BLOCK_DATA};
+use crate::{
+    block::{Block, BlockId, BlockRegistry, Fluid},
+    direction::Direction,
+    materials::{ChunkMaterial, ATTRIBUTE_BLOCK_DATA, ATTRIBUTE_LIGHT},
+    state::AppState,
+    textures::BlocksTexture,
+};
 
 use super::{
-    Chunk, ChunkEntities, Chunks, DirtyChunks, Neighbors, WorldPlugin, CHUNK_HEIGHT, CHUNK_WIDTH,
+    Chunk, ChunkEntities, Chunks, DirtyChunks, Neighbors, WorldPlugin, CHUNK_WIDTH, SECTION_HEIGHT,
 };
 
 #[derive(Resource, Default, Debug)]
-pub(super) struct ChunkMeshingTasks(pub(super) HashMap<IVec2, Task<Mesh>>);
+pub(super) struct ChunkMeshingTasks(pub(super) HashMap<IVec3, Task<SectionMeshes>>);
+
+/// Every section is split into a mesh of opaque and cutout blocks, and a mesh of translucent
+/// blocks drawn with alpha blending.
+#[derive(EnumIter, EnumCount, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub(super) enum MeshLayer {
+    Solid,
+    Translucent,
+}
+
+/// Meshes of a section indexed by [`MeshLayer`], or `None` for layers with no visible faces.
+pub(super) type SectionMeshes = [Option<Mesh>; MeshLayer::COUNT];
+
+#[derive(Resource, Debug)]
+pub(super) struct ChunkMaterials([Handle<ChunkMaterial>; MeshLayer::COUNT]);
+
+impl MeshLayer {
+    fn of(block: &Block) -> Self {
+        if block.is_translucent() {
+            Self::Translucent
+        } else {
+            Self::Solid
+        }
+    }
+}
+
+impl ChunkMaterials {
+    pub(super) fn new(
+        texture: &Handle<Image>,
+        blocks: &BlockRegistry,
+        materials: &mut Assets<ChunkMaterial>,
+    ) -> Self {
+        Self([
+            materials.add(ChunkMaterial::new(texture, blocks, false)),
+            materials.add(ChunkMaterial::new(texture, blocks, true)),
+        ])
+    }
+
+    pub(super) fn get(&self, layer: MeshLayer) -> &Handle<ChunkMaterial> {
+        &self.0[layer as usize]
+    }
+}
 
 impl Chunk {
-    pub(super) fn get_mesh(&self, neighbors: &Neighbors) -> Mesh {
-        let mut vertices = Vec::new();
-        let mut indices = Vec::new();
+    const MAX_QUAD_SIZE: usize = 16;
 
-        for (i, block) in self.0.into_iter().enumerate() {
-            if block.is_transparent() {
-                continue;
-            }
-            let x = i % CHUNK_WIDTH;
-            let z = (i / CHUNK_WIDTH) % CHUNK_WIDTH;
-            let y = (i / CHUNK_WIDTH / CHUNK_WIDTH) % CHUNK_HEIGHT;
+    /// Corners of a block's faces, in the same order as `block_vertices` in `chunk.wgsl`.
+    #[rustfmt::skip]
+    const FACE_VERTICES: [[IVec3; 4]; 6] = [
+        [IVec3::new(0, 0, 1), IVec3::new(1, 0, 1), IVec3::new(1, 1, 1), IVec3::new(0, 1, 1)], // north
+        [IVec3::new(1, 0, 0), IVec3::new(0, 0, 0), IVec3::new(0, 1, 0), IVec3::new(1, 1, 0)], // south
+        [IVec3::new(1, 0, 1), IVec3::new(1, 0, 0), IVec3::new(1, 1, 0), IVec3::new(1, 1, 1)], // west
+        [IVec3::new(0, 0, 0), IVec3::new(0, 0, 1), IVec3::new(0, 1, 1), IVec3::new(0, 1, 0)], // east
+        [IVec3::new(1, 1, 0), IVec3::new(0, 1, 0), IVec3::new(0, 1, 1), IVec3::new(1, 1, 1)], // up
+        [IVec3::new(1, 0, 1), IVec3::new(0, 0, 1), IVec3::new(0, 0, 0), IVec3::new(1, 0, 0)], // down
+    ];
+
+    /// Builds the meshes of a single section.
+    pub(super) fn get_mesh(
+        &self,
+        section: usize,
+        neighbors: &Neighbors,
+        blocks: &BlockRegistry,
+    ) -> SectionMeshes {
+        let section_blocks = &self.sections[section];
+        if section_blocks.is_empty() {
+            return Default::default();
+        }
+
+        let section_pos = IVec3::new(0, (section * SECTION_HEIGHT) as i32, 0);
+        let mut vertices: [Vec<i32>; MeshLayer::COUNT] = Default::default();
+        let mut indices: [Vec<u32>; MeshLayer::COUNT] = Default::default();
+        let mut lights: [Vec<u32>; MeshLayer::COUNT] = Default::default();
 
-            let local_pos = IVec3::new(x as i32, y as i32, z as i32);
+        for dir in Direction::iter() {
+            let (layers, width, height) = face_extents(dir);
+            let mut mask = vec![None; width * height];
 
-            for dir in Direction::iter() {
-                let neighbor_pos = local_pos + IVec3::from(dir);
-                if neighbor_pos.y < 0 || self.block_at(neighbors, neighbor_pos).is_opaque() {
-                    continue;
+            for layer in 0..layers {
+                for v in 0..height {
+                    for u in 0..width {
+                        let pos = face_pos(dir, layer, u, v);
+                        let block = section_blocks.get(pos);
+
+                        let neighbor_pos = section_pos + pos + IVec3::from(dir);
+                        let is_visible =
+                            self.is_face_visible(neighbors, blocks, block, neighbor_pos);
+
+                        // Faces are lit by the block they face.
+                        mask[u + v * width] = is_visible.then(|| {
+                            (
+                                block,
+                                self.light_at(neighbors, neighbor_pos),
+                                self.ambient_occlusion(neighbors, blocks, neighbor_pos, dir),
+                                self.surface_depth(neighbors, blocks, block, section_pos + pos),
+                            )
+                        });
+                    }
                 }
-                indices.extend([0, 1, 2, 0, 2, 3].map(|idx| vertices.len() as u32 + idx));
-                let mut data = block as i32;
-                data = (data << 3) | dir as i32;
-                data = (data << (CHUNK_WIDTH.ilog2() * 2 + CHUNK_HEIGHT.ilog2())) | i as i32;
 
-                vertices.extend([data; 4]);
+                for v in 0..height {
+                    let mut u = 0;
+                    while u < width {
+                        let Some(face @ (block, light, ao, depth)) = mask[u + v * width] else {
+                            u += 1;
+                            continue;
+                        };
+
+                        // Faces with uneven occlusion are not merged, as it would be stretched over
+                        // the whole quad.
+                        let max_quad_size = if ao.iter().all(|&level| level == ao[0]) {
+                            Self::MAX_QUAD_SIZE
+                        } else {
+                            1
+                        };
+
+                        let mut quad_width = 1;
+                        while quad_width < max_quad_size
+                            && u + quad_width < width
+                            && mask[u + quad_width + v * width] == Some(face)
+                        {
+                            quad_width += 1;
+                        }
+
+                        let mut quad_height = 1;
+                        while quad_height < max_quad_size
+                            && v + quad_height < height
+                            && (u..u + quad_width)
+                                .all(|u| mask[u + (v + quad_height) * width] == Some(face))
+                        {
+                            quad_height += 1;
+                        }
+
+                        for v in v..v + quad_height {
+                            mask[u + v * width..u + quad_width + v * width].fill(None);
+                        }
+
+                        let pos = face_pos(dir, layer, u, v);
+                        let i = pos.x as usize
+                            + pos.y as usize * CHUNK_WIDTH * CHUNK_WIDTH
+                            + pos.z as usize * CHUNK_WIDTH;
+
+                        let layer = MeshLayer::of(&blocks[block]) as usize;
+                        let (vertices, indices) = (&mut vertices[layer], &mut indices[layer]);
+                        lights[layer].extend(ao.map(|ao| {
+                            (depth as u32) << (u8::BITS + 2)
+                                | (ao as u32) << u8::BITS
+                                | light as u32
+                        }));
+
+                        // Split the quad along the darker diagonal, so that occlusion is
+                        // interpolated the same way regardless of which corner is occluded.
+                        let quad_indices = if ao[0] + ao[2] > ao[1] + ao[3] {
+                            [1, 2, 3, 1, 3, 0]
+                        } else {
+                            [0, 1, 2, 0, 2, 3]
+                        };
+                        indices.extend(quad_indices.map(|idx| vertices.len() as u32 + idx));
+                        let mut data = quad_height as i32 - 1;
+                        data = (data << Self::MAX_QUAD_SIZE.ilog2()) | (quad_width as i32 - 1);
+                        data = (data << u8::BITS) | u8::from(block) as i32;
+                        data = (data << 3) | dir as i32;
+                        data =
+                            (data << (CHUNK_WIDTH.ilog2() * 2 + SECTION_HEIGHT.ilog2())) | i as i32;
+
+                        vertices.extend([data; 4]);
+
+                        u += quad_width;
+                    }
+                }
             }
         }
 
-        Mesh::new(
-            PrimitiveTopology::TriangleList,
-            RenderAssetUsages::default(),
-        )
-        .with_inserted_attribute(ATTRIBUTE_BLOCK_DATA, vertices)
-        .with_inserted_indices(Indices::U32(indices))
+        let mut indices = indices.into_iter();
+        let mut lights = lights.into_iter();
+        vertices.map(|vertices| {
+            let indices = indices.next().unwrap();
+            let lights = lights.next().unwrap();
+            if vertices.is_empty() {
+                return None;
+            }
+
+            Some(
+                Mesh::new(
+                    PrimitiveTopology::TriangleList,
+                    RenderAssetUsages::default(),
+                )
+                .with_inserted_attribute(ATTRIBUTE_BLOCK_DATA, vertices)
+                .with_inserted_attribute(ATTRIBUTE_LIGHT, lights)
+                .with_inserted_indices(Indices::U32(indices)),
+            )
+        })
+    }
+}
+
+impl Chunk {
+    /// Whether a face of `block` is drawn, given the position of the block it faces. Opaque blocks
+    /// hide the faces next to them, and translucent blocks hide the faces between blocks of the
+    /// same kind, or of the same fluid.
+    fn is_face_visible(
+        &self,
+        neighbors: &Neighbors,
+        blocks: &BlockRegistry,
+        block: BlockId,
+        neighbor_pos: IVec3,
+    ) -> bool {
+        let neighbor = self.block_at(neighbors, neighbor_pos);
+        let is_same = neighbor == block
+            || blocks[block]
+                .fluid()
+                .is_some_and(|fluid| fluid.is_same(blocks[neighbor].fluid()));
+        let is_hidden = blocks[neighbor].is_opaque() || (blocks[block].is_translucent() && is_same);
+        !blocks[block].is_transparent() && neighbor_pos.y >= 0 && !is_hidden
+    }
+
+    /// How far the top of a block is lowered, in ninths of a block. Flowing fluid is lowered by
+    /// the levels it is missing, unless the same fluid is above it.
+    fn surface_depth(
+        &self,
+        neighbors: &Neighbors,
+        blocks: &BlockRegistry,
+        block: BlockId,
+        pos: IVec3,
+    ) -> u8 {
+        let Some(fluid) = blocks[block].fluid() else {
+            return 0;
+        };
+        if fluid.is_same(blocks[self.block_at(neighbors, pos + IVec3::Y)].fluid()) {
+            return 0;
+        }
+        Fluid::MAX_LEVEL - fluid.level()
+    }
+
+    /// Occlusion level of every corner of a face, from 0 (fully occluded) to 3, determined by the
+    /// opaque blocks around the corner in front of the face.
+    fn ambient_occlusion(
+        &self,
+        neighbors: &Neighbors,
+        blocks: &BlockRegistry,
+        pos: IVec3,
+        dir: Direction,
+    ) -> [u8; 4] {
+        let (u, v) = match dir {
+            Direction::North | Direction::South => (IVec3::X, IVec3::Y),
+            Direction::West | Direction::East => (IVec3::Z, IVec3::Y),
+            Direction::Up | Direction::Down => (IVec3::X, IVec3::Z),
+        };
+        let is_occluder = |offset| blocks[self.block_at(neighbors, pos + offset)].is_opaque();
+
+        Self::FACE_VERTICES[dir as usize].map(|corner| {
+            let offset = corner * 2 - IVec3::ONE;
+            let (side1, side2) = (offset * u, offset * v);
+
+            match (is_occluder(side1), is_occluder(side2)) {
+                (true, true) => 0,
+                (a, b) => 3 - a as u8 - b as u8 - is_occluder(side1 + side2) as u8,
+            }
+        })
     }
 }
 
 impl WorldPlugin {
+    pub(super) fn create_chunk_materials(
+        mut commands: Commands,
+        texture: Res<BlocksTexture>,
+        blocks: Res<BlockRegistry>,
+        mut materials: ResMut<Assets<ChunkMaterial>>,
+    ) {
+        commands.insert_resource(ChunkMaterials::new(&texture.0, &blocks, &mut materials));
+    }
+
     pub(super) fn handle_meshing_tasks(
         mut commands: Commands,
-        entities: Res<ChunkEntities>,
+        materials: Res<ChunkMaterials>,
+        mut entities: ResMut<ChunkEntities>,
         mut tasks: ResMut<ChunkMeshingTasks>,
         mut meshes: ResMut<Assets<Mesh>>,
     ) {
-        tasks.0.retain(|offset, task| {
-            if let Some(mesh) = block_on(future::poll_once(task)) {
-                if let Some(&entity) = entities.0.get(offset) {
-                    commands.entity(entity).insert(meshes.add(mesh));
+        tasks.0.retain(|&offset, task| {
+            let Some(section_meshes) = block_on(future::poll_once(task)) else {
+                return true;
+            };
+
+            for (layer, mesh) in MeshLayer::iter().zip(section_meshes) {
+                let key = (offset, layer);
+                match (mesh, entities.0.get(&key)) {
+                    (Some(mesh), Some(&entity)) => {
+                        commands.entity(entity).insert(meshes.add(mesh));
+                    }
+                    (Some(mesh), None) => {
+                        let entity = commands
+                            .spawn(section_bundle(
+                                offset,
+                                meshes.add(mesh),
+                                materials.get(layer).clone(),
+                            ))
+                            .id();
+                        entities.0.insert(key, entity);
+                    }
+                    (None, Some(_)) => {
+                        let entity = entities.0.remove(&key).unwrap();
+                        commands.entity(entity).despawn();
+                    }
+                    (None, None) => {}
                 }
-                false
-            } else {
-                true
             }
+
+            false
         });
     }
 
     pub(super) fn mesh_chunks(
         chunks: Res<Chunks>,
+        blocks: Res<BlockRegistry>,
         mut dirty: ResMut<DirtyChunks>,
         mut tasks: ResMut<ChunkMeshingTasks>,
     ) {
         let thread_pool = AsyncComputeTaskPool::get();
 
         for &offset in dirty.0.iter() {
-            let Some(chunk) = chunks.0.get(&offset).cloned() else {
-                return;
+            let Some(chunk) = chunks.0.get(&offset.xz()).cloned() else {
+                continue;
             };
-            let neighbors = chunks.get_neighbors(offset);
-            let task = thread_pool.spawn(async move { chunk.get_mesh(&neighbors) });
+            let neighbors = chunks.get_neighbors(offset.xz());
+            let blocks = blocks.clone();
+            let task = thread_pool
+                .spawn(async move { chunk.get_mesh(offset.y as usize, &neighbors, &blocks) });
             tasks.0.insert(offset, task);
         }
 
         dirty.0.clear();
     }
 }
+
+/// Components of a section entity. The entity is placed at the center of the section so that
+/// translucent sections are sorted by distance correctly. Vertex positions are decoded in the
+/// shader, so the bounding box used for frustum culling has to be provided explicitly.
+fn section_bundle(
+    offset: IVec3,
+    mesh: Handle<Mesh>,
+    material: Handle<ChunkMaterial>,
+) -> impl Bundle {
+    let size = Vec3::new(
+        CHUNK_WIDTH as f32,
+        SECTION_HEIGHT as f32,
+        CHUNK_WIDTH as f32,
+    );
+
+    (
+        MaterialMeshBundle {
+            mesh,
+            material,
+            transform: Transform::from_translation(offset.as_vec3() * size + size / 2.0),
+            ..Default::default()
+        },
+        Aabb::from_min_max(-size / 2.0, size / 2.0),
+        StateScoped(AppState::InGame),
+    )
+}
+
+/// Number of layers of faces facing the direction in a section, and the number of faces along
+/// the `u` and `v` axes of a layer, see [`face_pos`].
+fn face_extents(dir: Direction) -> (usize, usize, usize) {
+    match dir {
+        Direction::North | Direction::South | Direction::West | Direction::East => {
+            (CHUNK_WIDTH, CHUNK_WIDTH, SECTION_HEIGHT)
+        }
+        Direction::Up | Direction::Down => (SECTION_HEIGHT, CHUNK_WIDTH, CHUNK_WIDTH),
+    }
+}
+
+fn face_pos(dir: Direction, layer: usize, u: usize, v: usize) -> IVec3 {
+    let (x, y, z) = match dir {
+        Direction::North | Direction::South => (u, v, layer),
+        Direction::West | Direction::East => (layer, v, u),
+        Direction::Up | Direction::Down => (u, layer, v),
+    };
+    IVec3::new(x as i32, y as i32, z as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, sync::Arc};
+
+    use bevy::render::mesh::VertexAttributeValues;
+
+    use crate::toml_asset::TomlAsset;
+
+    use super::{
+        super::{Noise, WorldgenParams, CHUNK_SECTIONS, CHUNK_VOLUME, SECTION_VOLUME},
+        *,
+    };
+
+    /// Chunk whose second section is filled by the predicate with stone, and air elsewhere.
+    fn chunk(blocks: &BlockRegistry, is_stone: impl Fn(IVec3) -> bool) -> Chunk {
+        let stone = blocks.id("stone").unwrap();
+        let mut chunk = Chunk::from_blocks(&[BlockId::AIR; CHUNK_VOLUME]);
+        for x in 0..CHUNK_WIDTH as i32 {
+            for y in 0..SECTION_HEIGHT as i32 {
+                for z in 0..CHUNK_WIDTH as i32 {
+                    let pos = IVec3::new(x, y, z);
+                    if is_stone(pos) {
+                        chunk.set(pos + IVec3::Y * SECTION_HEIGHT as i32, stone);
+                    }
+                }
+            }
+        }
+        chunk
+    }
+
+    fn greedy_quad_count(
+        chunk: &Chunk,
+        section: usize,
+        neighbors: &Neighbors,
+        blocks: &BlockRegistry,
+    ) -> usize {
+        chunk
+            .get_mesh(section, neighbors, blocks)
+            .iter()
+            .flatten()
+            .map(|mesh| mesh.attribute(ATTRIBUTE_BLOCK_DATA).unwrap().len() / 4)
+            .sum()
+    }
+
+    /// Quads emitted by a mesher without merging, one per visible face.
+    fn naive_quad_count(
+        chunk: &Chunk,
+        section: usize,
+        neighbors: &Neighbors,
+        blocks: &BlockRegistry,
+    ) -> usize {
+        let mut count = 0;
+        for x in 0..CHUNK_WIDTH as i32 {
+            for y in 0..SECTION_HEIGHT as i32 {
+                for z in 0..CHUNK_WIDTH as i32 {
+                    let pos = IVec3::new(x, y, z);
+                    let block = chunk.sections[section].get(pos);
+                    let pos = pos + IVec3::Y * (section * SECTION_HEIGHT) as i32;
+                    count += Direction::iter()
+                        .filter(|&dir| {
+                            let neighbor_pos = pos + IVec3::from(dir);
+                            chunk.is_face_visible(neighbors, blocks, block, neighbor_pos)
+                        })
+                        .count();
+                }
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn greedy_meshing_merges_solid_section() {
+        let blocks = BlockRegistry::from_assets();
+        let chunk = chunk(&blocks, |_| true);
+        let neighbors = Default::default();
+
+        assert_eq!(
+            naive_quad_count(&chunk, 1, &neighbors, &blocks),
+            6 * CHUNK_WIDTH * SECTION_HEIGHT
+        );
+        assert_eq!(greedy_quad_count(&chunk, 1, &neighbors, &blocks), 6);
+    }
+
+    #[test]
+    fn greedy_meshing_keeps_checkerboard() {
+        let blocks = BlockRegistry::from_assets();
+        let chunk = chunk(&blocks, |pos| (pos.x + pos.y + pos.z) % 2 == 0);
+        let neighbors = Default::default();
+
+        assert_eq!(
+            naive_quad_count(&chunk, 1, &neighbors, &blocks),
+            6 * SECTION_VOLUME / 2
+        );
+        assert_eq!(
+            greedy_quad_count(&chunk, 1, &neighbors, &blocks),
+            naive_quad_count(&chunk, 1, &neighbors, &blocks)
+        );
+    }
+
+    #[test]
+    fn flowing_fluid_surface_is_lowered() {
+        let blocks = BlockRegistry::from_assets();
+        let water = blocks[blocks.id("water").unwrap()].fluid().unwrap();
+        let mut chunk = Chunk::from_blocks(&[BlockId::AIR; CHUNK_VOLUME]);
+        let neighbors = Default::default();
+
+        let pos = IVec3::new(3, 20, 3);
+        for (block, depth) in [
+            (water.with_level(Fluid::MAX_LEVEL), 0),
+            (water.with_level(5), 3),
+            (water.with_level(1), 7),
+        ] {
+            chunk.set(pos, block);
+            assert_eq!(chunk.surface_depth(&neighbors, &blocks, block, pos), depth);
+        }
+
+        let block = water.with_level(5);
+        chunk.set(pos + IVec3::Y, water.with_level(2));
+        assert_eq!(chunk.surface_depth(&neighbors, &blocks, block, pos), 0);
+
+        let [_, translucent] = chunk.get_mesh(1, &neighbors, &blocks);
+        let lights = match translucent.unwrap().attribute(ATTRIBUTE_LIGHT).unwrap() {
+            VertexAttributeValues::Uint32(lights) => lights.clone(),
+            _ => unreachable!(),
+        };
+        let depths: Vec<_> = lights.iter().map(|light| light >> (u8::BITS + 2)).collect();
+        assert!(depths.contains(&0) && depths.contains(&(Fluid::MAX_LEVEL as u32 - 2)));
+    }
+
+    #[test]
+    fn greedy_meshing_reduces_generated_terrain() {
+        let blocks = BlockRegistry::from_assets();
+        let table = TomlAsset::read(Path::new("assets/worldgen.toml")).unwrap();
+        let params = WorldgenParams::from_toml(&table, &blocks).unwrap();
+        let noise = Noise::new(42);
+
+        for center in [IVec2::ZERO, IVec2::new(-7, 3), IVec2::new(20, -11)] {
+            let chunk = Chunk::generate(center, &noise, &params, &blocks).0;
+            let neighbors: Neighbors = array_init::array_init(|i| {
+                let neighbor = IVec2::new(i as i32 % 3, i as i32 / 3) - IVec2::ONE;
+                (neighbor != IVec2::ZERO).then(|| {
+                    Arc::new(Chunk::generate(center + neighbor, &noise, &params, &blocks).0)
+                })
+            });
+
+            let (mut naive, mut greedy) = (0, 0);
+            for section in 0..CHUNK_SECTIONS {
+                let section_naive = naive_quad_count(&chunk, section, &neighbors, &blocks);
+                let section_greedy = greedy_quad_count(&chunk, section, &neighbors, &blocks);
+                assert!(section_greedy <= section_naive);
+                naive += section_naive;
+                greedy += section_greedy;
+            }
+
+            println!("chunk {center}: {naive} quads naively, {greedy} greedily");
+            assert!(greedy < naive);
+        }
+    }
+}
@@ -1,55 +1,156 @@
+mod config;
 mod db;
+mod edit;
+mod feature;
+mod fluid;
 mod gen;
+mod light;
+mod map;
 mod mesh;
+mod pregen;
+mod save;
+mod section;
 mod spawn;
+mod spawn_point;
 
 use std::sync::Arc;
 
 use array_init::array_init;
 use bevy::{
     prelude::*,
+    tasks::block_on,
     utils::{HashMap, HashSet},
 };
+use config::WorldgenErrors;
 use db::Db;
+use fluid::ActiveFluids;
 use gen::LoadingWorldgenParams;
-use mesh::ChunkMeshingTasks;
+use light::LightMap;
+use mesh::{ChunkMaterials, ChunkMeshingTasks, MeshLayer};
+use section::Section;
 use spawn::ChunkSpawningTasks;
 
 use crate::{
-    block::BlockId, direction::Direction, sets::LoadingSet, state::AppState,
-    textures::BlocksTexture,
+    block::{BlockId, BlockRegistry},
+    direction::Direction,
+    sets::{GameplaySet, LoadingSet},
+    settings::Settings,
+    state::AppState,
 };
 
 pub(super) use gen::{Noise, WorldgenParams};
+pub(super) use map::export_maps;
+pub(super) use pregen::{pregenerate, ChunkArea};
+pub(super) use save::{parse_seed, Save};
+pub(super) use spawn::GenerationProgress;
+pub(super) use spawn_point::WorldSpawn;
 
 pub(super) const CHUNK_WIDTH: usize = 16;
 const CHUNK_HEIGHT: usize = 256;
 const CHUNK_VOLUME: usize = CHUNK_WIDTH * CHUNK_WIDTH * CHUNK_HEIGHT;
-
-#[derive(Debug)]
-struct Chunk([BlockId; CHUNK_VOLUME]);
+const SECTION_HEIGHT: usize = 16;
+const SECTION_VOLUME: usize = CHUNK_WIDTH * CHUNK_WIDTH * SECTION_HEIGHT;
+const CHUNK_SECTIONS: usize = CHUNK_HEIGHT / SECTION_HEIGHT;
+
+#[derive(Clone, Debug)]
+struct Chunk {
+    sections: [Section; CHUNK_SECTIONS],
+    light: LightMap,
+}
 
 #[derive(Resource, Default, Debug)]
 pub(super) struct Chunks(HashMap<IVec2, Arc<Chunk>>);
 
+/// Sections waiting to be meshed, keyed by chunk offset with the section index as `y`.
 #[derive(Resource, Default, Debug)]
-struct DirtyChunks(HashSet<IVec2>);
+struct DirtyChunks(HashSet<IVec3>);
 
 #[derive(Resource, Default, Debug)]
-struct ChunkEntities(HashMap<IVec2, Entity>);
+struct ChunkEntities(HashMap<(IVec3, MeshLayer), Entity>);
+
+/// Chunks surrounding a chunk, indexed by `(x + 1) + (z + 1) * 3` of their offset relative to
+/// it. The middle one is always `None`.
+type Neighbors = [Option<Arc<Chunk>>; 9];
+
+#[derive(Clone, Copy, Debug)]
+pub(super) struct RayHit {
+    pub(super) pos: IVec3,
+    pub(super) block: BlockId,
+    pub(super) face: Direction,
+}
 
-type Neighbors = [Option<Arc<Chunk>>; 4];
+#[derive(Event, Debug)]
+pub(super) struct BlockEditEvent {
+    pub(super) pos: IVec3,
+    pub(super) block: BlockId,
+}
 
 #[derive(Debug)]
 pub(super) struct WorldPlugin;
 
 impl Chunk {
-    fn block_at(&self, neighbors: &Neighbors, pos: IVec3) -> BlockId {
-        debug_assert!(pos.xz().min_element() >= -1 && pos.xz().max_element() <= CHUNK_WIDTH as i32);
+    fn from_blocks(blocks: &[BlockId]) -> Self {
+        debug_assert_eq!(blocks.len(), CHUNK_VOLUME);
+        Self::from_sections(array_init(|section| {
+            Section::from_blocks(&blocks[section * SECTION_VOLUME..(section + 1) * SECTION_VOLUME])
+        }))
+    }
+
+    /// Creates a chunk with no light, see [`Chunk::compute_light`].
+    fn from_sections(sections: [Section; CHUNK_SECTIONS]) -> Self {
+        Self {
+            sections,
+            light: LightMap::default(),
+        }
+    }
+
+    fn get(&self, pos: IVec3) -> BlockId {
+        let (section, pos) = Self::section_pos(pos);
+        self.sections[section].get(pos)
+    }
+
+    fn set(&mut self, pos: IVec3, block: BlockId) {
+        let (section, pos) = Self::section_pos(pos);
+        self.sections[section].set(pos, block);
+    }
+
+    fn section_pos(pos: IVec3) -> (usize, IVec3) {
+        let section = pos.y as usize / SECTION_HEIGHT;
+        let pos = IVec3::new(pos.x, pos.y % SECTION_HEIGHT as i32, pos.z);
+        (section, pos)
+    }
 
+    fn block_at(&self, neighbors: &Neighbors, pos: IVec3) -> BlockId {
         if pos.y < 0 || pos.y >= CHUNK_HEIGHT as i32 {
-            return BlockId::Air;
+            return BlockId::AIR;
+        }
+
+        match self.resolve(neighbors, pos) {
+            Some((chunk, pos)) => chunk.get(pos),
+            None => BlockId::AIR,
+        }
+    }
+
+    /// Packed light of the block, see [`LightMap`]. Blocks in unloaded chunks are fully lit by the
+    /// sky.
+    fn light_at(&self, neighbors: &Neighbors, pos: IVec3) -> u8 {
+        if pos.y < 0 {
+            return 0;
+        }
+        if pos.y >= CHUNK_HEIGHT as i32 {
+            return LightMap::SKY;
+        }
+
+        match self.resolve(neighbors, pos) {
+            Some((chunk, pos)) => chunk.light.get(pos),
+            None => LightMap::SKY,
         }
+    }
+
+    /// Returns the chunk containing a position next to or inside this chunk, along with the
+    /// position local to it.
+    fn resolve<'a>(&'a self, neighbors: &'a Neighbors, pos: IVec3) -> Option<(&'a Chunk, IVec3)> {
+        debug_assert!(pos.xz().min_element() >= -1 && pos.xz().max_element() <= CHUNK_WIDTH as i32);
 
         let offset = pos.xz().div_euclid(IVec2::splat(CHUNK_WIDTH as i32));
 
@@ -61,19 +162,12 @@ impl Chunk {
 
         let chunk = match offset {
             IVec2::ZERO => Some(self),
-            _ => neighbors[Direction::try_from(offset).unwrap() as usize]
+            _ => neighbors[(offset.x + 1) as usize + (offset.y + 1) as usize * 3]
                 .as_ref()
                 .map(Arc::as_ref),
         };
 
-        match chunk {
-            Some(chunk) => {
-                let i =
-                    pos.x + pos.y * (CHUNK_WIDTH * CHUNK_WIDTH) as i32 + pos.z * CHUNK_WIDTH as i32;
-                chunk.0.get(i as usize).cloned().unwrap_or(BlockId::Air)
-            }
-            None => BlockId::Air,
-        }
+        chunk.map(|chunk| (chunk, pos))
     }
 }
 
@@ -89,23 +183,39 @@ impl Chunks {
                     pos.z.div_euclid(CHUNK_WIDTH as i32),
                 ) * CHUNK_WIDTH as i32;
 
-            chunk.block_at(&self.get_neighbors(offset), local_pos)
+            if pos.y < 0 || pos.y >= CHUNK_HEIGHT as i32 {
+                BlockId::AIR
+            } else {
+                chunk.get(local_pos)
+            }
         })
     }
 
+    fn set_block_at(&mut self, pos: IVec3, block: BlockId) -> Option<IVec2> {
+        if pos.y < 0 || pos.y >= CHUNK_HEIGHT as i32 {
+            return None;
+        }
+
+        let offset = pos.xz().div_euclid(IVec2::splat(CHUNK_WIDTH as i32));
+        let chunk = self.0.get_mut(&offset)?;
+
+        let local_pos = pos - IVec3::new(offset.x, 0, offset.y) * CHUNK_WIDTH as i32;
+        Arc::make_mut(chunk).set(local_pos, block);
+
+        Some(offset)
+    }
+
     fn get_neighbors(&self, offset: IVec2) -> Neighbors {
         array_init(|i| {
-            let dir = [
-                Direction::North,
-                Direction::South,
-                Direction::West,
-                Direction::East,
-            ][i];
-            self.0.get(&(offset + IVec2::from(dir))).cloned()
+            let neighbor = IVec2::new(i as i32 % 3, i as i32 / 3) - IVec2::ONE;
+            if neighbor == IVec2::ZERO {
+                return None;
+            }
+            self.0.get(&(offset + neighbor)).cloned()
         })
     }
 
-    pub(super) fn traverse(&self, ray: Ray3d, max: f32) -> Option<(IVec3, BlockId)> {
+    pub(super) fn traverse(&self, ray: Ray3d, max: f32, blocks: &BlockRegistry) -> Option<RayHit> {
         fn offset(start: f32, diff: f32) -> f32 {
             if diff.is_sign_positive() {
                 start.floor() + 1.0 - start
@@ -132,20 +242,49 @@ impl Chunks {
             + (end.y.floor() - start.y.floor()).abs()
             + (end.z.floor() - start.z.floor()).abs()) as i32;
 
+        // The block containing the origin is treated as entered through the face the ray points
+        // away from along its dominant axis.
+        let abs = dir.abs();
+        let mut normal = if abs.x >= abs.y && abs.x >= abs.z {
+            IVec3::new(-step.x, 0, 0)
+        } else if abs.y >= abs.z {
+            IVec3::new(0, -step.y, 0)
+        } else {
+            IVec3::new(0, 0, -step.z)
+        };
+        let mut t = 0.0;
+
         for _ in 0..=dist {
-            if let Some(block) = self.block_at(pos).take_if(|block| block.is_solid()) {
-                return Some((pos, block));
+            if t > max {
+                break;
+            }
+
+            if let Some(block) = self
+                .block_at(pos)
+                .take_if(|&mut block| blocks[block].is_solid())
+            {
+                return Some(RayHit {
+                    pos,
+                    block,
+                    face: Direction::try_from(normal).unwrap(),
+                });
             }
 
             if t_max.x < t_max.y && t_max.x < t_max.z {
                 pos.x += step.x;
+                t = t_max.x;
                 t_max.x += t_delta.x;
+                normal = IVec3::new(-step.x, 0, 0);
             } else if t_max.y < t_max.z {
                 pos.y += step.y;
+                t = t_max.y;
                 t_max.y += t_delta.y;
+                normal = IVec3::new(0, -step.y, 0);
             } else {
                 pos.z += step.z;
+                t = t_max.z;
                 t_max.z += t_delta.z;
+                normal = IVec3::new(0, 0, -step.z);
             }
         }
 
@@ -154,47 +293,124 @@ impl Chunks {
 }
 
 impl DirtyChunks {
+    /// Marks a chunk and all chunks around it, whose meshes depend on it.
     pub(super) fn insert(&mut self, offset: IVec2) {
-        self.0.insert(offset);
-
-        for dir in [
-            Direction::North,
-            Direction::South,
-            Direction::West,
-            Direction::East,
-        ] {
-            self.0.insert(offset + IVec2::from(dir));
+        for x in -1..=1 {
+            for z in -1..=1 {
+                self.insert_sections(offset + IVec2::new(x, z));
+            }
+        }
+    }
+
+    fn insert_sections(&mut self, offset: IVec2) {
+        self.0.extend(
+            (0..CHUNK_SECTIONS as i32).map(|section| IVec3::new(offset.x, section, offset.y)),
+        );
+    }
+
+    /// Marks the sections containing a block or any block around it, whose faces are affected by
+    /// it through culling, light and ambient occlusion.
+    fn insert_block(&mut self, pos: IVec3) {
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    let pos = pos + IVec3::new(x, y, z);
+                    let section = pos.div_euclid(IVec3::new(
+                        CHUNK_WIDTH as i32,
+                        SECTION_HEIGHT as i32,
+                        CHUNK_WIDTH as i32,
+                    ));
+                    if (0..CHUNK_SECTIONS as i32).contains(&section.y) {
+                        self.0.insert(section);
+                    }
+                }
+            }
         }
     }
 }
 
+impl BlockEditEvent {
+    pub(super) fn new(pos: IVec3, block: BlockId) -> Self {
+        Self { pos, block }
+    }
+}
+
 impl Plugin for WorldPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<Chunks>()
+        app.add_event::<BlockEditEvent>()
+            .init_resource::<Chunks>()
             .init_resource::<DirtyChunks>()
             .init_resource::<ChunkEntities>()
             .init_resource::<ChunkSpawningTasks>()
             .init_resource::<ChunkMeshingTasks>()
-            .init_resource::<Noise>()
+            .init_resource::<ActiveFluids>()
             .init_resource::<LoadingWorldgenParams>()
-            .init_resource::<Db>()
-            .add_systems(OnEnter(AppState::Generating), Self::generate_world)
-            .add_systems(Update, (Self::create_worldgen_params).in_set(LoadingSet))
+            .add_systems(
+                OnEnter(AppState::Generating),
+                (
+                    Self::open_world,
+                    Self::create_chunk_materials,
+                    Self::start_generating,
+                )
+                    .chain(),
+            )
+            .add_systems(OnExit(AppState::InGame), Self::close_world)
+            .add_systems(
+                Update,
+                (
+                    Self::create_worldgen_params.run_if(resource_exists::<BlockRegistry>),
+                    Self::display_worldgen_errors
+                        .run_if(resource_changed_or_removed::<WorldgenErrors>()),
+                )
+                    .chain()
+                    .in_set(LoadingSet),
+            )
+            .add_systems(OnExit(AppState::Loading), Self::despawn_worldgen_errors)
             .add_systems(
                 Update,
                 (
-                    Self::despawn_chunks,
                     (
-                        Self::sync_dirty_chunks,
-                        Self::sync_chunk_entities.run_if(resource_exists::<BlocksTexture>),
-                    ),
+                        Self::reload_worldgen_params,
+                        Self::regenerate_chunks
+                            .run_if(resource_exists_and_changed::<WorldgenParams>)
+                            .run_if(in_state(AppState::InGame)),
+                    )
+                        .chain()
+                        .run_if(resource_exists::<WorldgenParams>),
                     (
-                        Self::spawn_chunks.run_if(resource_exists::<WorldgenParams>),
-                        Self::handle_meshing_tasks,
-                        (Self::handle_spawning_tasks, Self::mesh_chunks).chain(),
-                    ),
+                        Self::update_render_distance
+                            .run_if(resource_exists_and_changed::<Settings>),
+                        Self::despawn_chunks.run_if(resource_exists::<Settings>),
+                        (Self::sync_dirty_chunks, Self::sync_chunk_entities),
+                        (
+                            Self::spawn_chunks
+                                .run_if(resource_exists::<WorldgenParams>)
+                                .run_if(resource_exists::<Settings>),
+                            Self::handle_meshing_tasks.run_if(resource_exists::<ChunkMaterials>),
+                            (
+                                Self::handle_spawning_tasks,
+                                (Self::edit_blocks, Self::activate_fluids),
+                                Self::mesh_chunks,
+                            )
+                                .chain(),
+                        ),
+                        (
+                            Self::update_generation_progress,
+                            Self::find_world_spawn.run_if(not(resource_exists::<WorldSpawn>)),
+                        )
+                            .chain()
+                            .run_if(in_state(AppState::Generating)),
+                    )
+                        .chain()
+                        .run_if(in_state(AppState::Generating).or_else(in_state(AppState::InGame))),
                 )
                     .chain(),
+            )
+            .add_systems(
+                FixedUpdate,
+                (Self::update_fluids)
+                    .run_if(resource_exists::<BlockRegistry>)
+                    .in_set(GameplaySet),
             );
     }
 }
@@ -204,7 +420,149 @@ impl WorldPlugin {
         params.is_some()
     }
 
-    pub(super) fn is_generated(chunks: Res<Chunks>) -> bool {
-        !chunks.0.is_empty()
+    /// Whether the chunks around the spawn are generated and meshed, and the spawn is known.
+    pub(super) fn is_generated(
+        progress: Option<Res<GenerationProgress>>,
+        spawn: Option<Res<WorldSpawn>>,
+    ) -> bool {
+        progress.is_some_and(|progress| progress.is_done()) && spawn.is_some()
+    }
+
+    /// Opens the database and the noise of the world in [`Save`].
+    fn open_world(world: &mut World) {
+        world.init_resource::<Db>();
+        world.init_resource::<Noise>();
+    }
+
+    /// Unloads the world when leaving the game, so that another one can be opened. Its entities
+    /// are scoped to [`AppState::InGame`].
+    fn close_world(mut commands: Commands, db: Res<Db>) {
+        block_on(db.0.close());
+        commands.remove_resource::<Db>();
+        commands.remove_resource::<Noise>();
+        commands.remove_resource::<Save>();
+        commands.remove_resource::<GenerationProgress>();
+        commands.remove_resource::<WorldSpawn>();
+
+        commands.insert_resource(Chunks::default());
+        commands.insert_resource(DirtyChunks::default());
+        commands.insert_resource(ChunkEntities::default());
+        commands.insert_resource(ChunkSpawningTasks::default());
+        commands.insert_resource(ChunkMeshingTasks::default());
+        commands.insert_resource(ActiveFluids::default());
+    }
+}
+
+/// Splits a position into the offset of the chunk containing it and the position local to it.
+fn world_to_local(pos: IVec3) -> (IVec2, IVec3) {
+    let offset = pos.xz().div_euclid(IVec2::splat(CHUNK_WIDTH as i32));
+    (
+        offset,
+        pos - IVec3::new(offset.x, 0, offset.y) * CHUNK_WIDTH as i32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+
+    const REACH: f32 = 10.0;
+
+    /// Chunks at the given offsets, filled with air except for stone at the given positions.
+    fn chunks(offsets: &[IVec2], stones: &[IVec3], blocks: &BlockRegistry) -> Chunks {
+        let mut chunks = Chunks::default();
+        for offset in offsets {
+            let chunk = Chunk::from_blocks(&[BlockId::AIR; CHUNK_VOLUME]);
+            chunks.0.insert(*offset, Arc::new(chunk));
+        }
+        for pos in stones {
+            chunks.set_block_at(*pos, blocks.id("stone").unwrap());
+        }
+        chunks
+    }
+
+    fn traverse(chunks: &Chunks, origin: Vec3, dir: Vec3, blocks: &BlockRegistry) -> RayHit {
+        let ray = Ray3d::new(origin, dir);
+        chunks.traverse(ray, REACH, blocks).unwrap()
+    }
+
+    #[test]
+    fn traverse_along_axes() {
+        let blocks = BlockRegistry::from_assets();
+        let center = IVec3::new(8, 100, 8);
+        let origin = center.as_vec3() + 0.5;
+
+        for dir in Direction::iter() {
+            let dir = IVec3::from(dir);
+            let target = center + dir * 3;
+            let chunks = chunks(&[IVec2::ZERO], &[target], &blocks);
+
+            let hit = traverse(&chunks, origin, dir.as_vec3(), &blocks);
+            assert_eq!(hit.pos, target);
+            assert_eq!(hit.block, blocks.id("stone").unwrap());
+            assert_eq!(IVec3::from(hit.face), -dir);
+        }
+    }
+
+    #[test]
+    fn traverse_diagonally() {
+        let blocks = BlockRegistry::from_assets();
+        let origin = Vec3::new(0.5, 100.5, 0.5);
+        let chunks = chunks(
+            &[IVec2::ZERO],
+            &[IVec3::new(3, 101, 1), IVec3::new(1, 98, 3)],
+            &blocks,
+        );
+
+        let hit = traverse(&chunks, origin, Vec3::new(3.0, 1.0, 1.0), &blocks);
+        assert_eq!(hit.pos, IVec3::new(3, 101, 1));
+        assert_eq!(hit.face, Direction::East);
+
+        let hit = traverse(&chunks, origin, Vec3::new(1.0, -2.0, 3.0), &blocks);
+        assert_eq!(hit.pos, IVec3::new(1, 98, 3));
+        assert_eq!(hit.face, Direction::South);
+    }
+
+    #[test]
+    fn traverse_from_inside_block() {
+        let blocks = BlockRegistry::from_assets();
+        let origin = Vec3::new(4.25, 100.5, 4.5);
+        let chunks = chunks(&[IVec2::ZERO], &[origin.floor().as_ivec3()], &blocks);
+
+        let hit = traverse(&chunks, origin, Vec3::new(1.0, 0.5, 0.0), &blocks);
+        assert_eq!(hit.pos, origin.floor().as_ivec3());
+        assert_eq!(hit.face, Direction::East);
+    }
+
+    #[test]
+    fn traverse_across_negative_chunk_border() {
+        let blocks = BlockRegistry::from_assets();
+        let origin = Vec3::new(1.5, 100.5, 0.5);
+        let chunks = chunks(
+            &[IVec2::ZERO, IVec2::new(-1, 0), IVec2::new(0, -1)],
+            &[IVec3::new(-2, 100, 0), IVec3::new(1, 100, -3)],
+            &blocks,
+        );
+
+        let hit = traverse(&chunks, origin, Vec3::NEG_X, &blocks);
+        assert_eq!(hit.pos, IVec3::new(-2, 100, 0));
+        assert_eq!(hit.face, Direction::West);
+
+        let hit = traverse(&chunks, origin, Vec3::NEG_Z, &blocks);
+        assert_eq!(hit.pos, IVec3::new(1, 100, -3));
+        assert_eq!(hit.face, Direction::North);
+    }
+
+    #[test]
+    fn traverse_stops_at_max_distance() {
+        let blocks = BlockRegistry::from_assets();
+        let origin = Vec3::new(0.5, 100.5, 0.5);
+        let chunks = chunks(&[IVec2::ZERO], &[IVec3::new(5, 100, 0)], &blocks);
+
+        let ray = Ray3d::new(origin, Vec3::X);
+        assert!(chunks.traverse(ray, 4.0, &blocks).is_none());
+        assert!(chunks.traverse(ray, 5.0, &blocks).is_some());
     }
 }
@@ -0,0 +1,149 @@
+use std::{path::Path, str::FromStr, time::Instant};
+
+use anyhow::{anyhow, Context};
+use bevy::{prelude::*, tasks::block_on};
+
+use crate::{block::BlockRegistry, toml_asset::TomlAsset};
+
+use super::{
+    db::Db,
+    spawn::{chunks_around, generate_chunks},
+    Noise, Save, WorldgenParams,
+};
+
+/// Rectangular or round area of chunks, in chunk offsets.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum ChunkArea {
+    /// Chunks within a distance of the origin.
+    Radius(u32),
+    /// Chunks between the minimum and the maximum corner, inclusive. The area is empty if the
+    /// minimum is greater than the maximum on either axis.
+    Rect(IVec2, IVec2),
+}
+
+impl ChunkArea {
+    /// Offsets of the chunks in the area, closest to the origin first, so that an interrupted
+    /// run leaves no holes around spawn.
+    pub(super) fn offsets(self) -> Vec<IVec2> {
+        let mut offsets: Vec<_> = match self {
+            Self::Radius(radius) => chunks_around(IVec2::ZERO, radius as i32).collect(),
+            Self::Rect(min, max) => (min.x..=max.x)
+                .flat_map(|x| (min.y..=max.y).map(move |z| IVec2::new(x, z)))
+                .collect(),
+        };
+        offsets.sort_by_key(|offset| offset.length_squared());
+        offsets
+    }
+}
+
+impl FromStr for ChunkArea {
+    type Err = anyhow::Error;
+
+    /// Parses either a radius, `16`, or two opposite corners, `-8,-8:8,8`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((a, b)) = s.split_once(':') else {
+            let radius: i32 = s.parse().with_context(|| format!("invalid radius `{s}`"))?;
+            let radius = u32::try_from(radius)
+                .map_err(|_| anyhow!("radius must not be negative, got {radius}"))?;
+            return Ok(Self::Radius(radius));
+        };
+
+        let corner = |s: &str| -> anyhow::Result<IVec2> {
+            let (x, z) = s
+                .split_once(',')
+                .ok_or_else(|| anyhow!("invalid corner `{s}`, expected `x,z`"))?;
+            Ok(IVec2::new(x.trim().parse()?, z.trim().parse()?))
+        };
+        let (a, b) = (corner(a)?, corner(b)?);
+        Ok(Self::Rect(a.min(b), a.max(b)))
+    }
+}
+
+/// Chunks generated and stored at once, after which progress is reported.
+const PREGEN_BATCH: usize = 256;
+
+/// Generates the chunks of `area` that aren't saved yet into the world, without a window.
+pub(crate) fn pregenerate(save: &Save, area: ChunkArea) -> anyhow::Result<()> {
+    let assets = Path::new("assets");
+    let blocks = BlockRegistry::from_toml(&TomlAsset::read(&assets.join("blocks.toml"))?)
+        .map_err(|errors| anyhow!("invalid `blocks.toml`:\n{errors}"))?;
+    let params =
+        WorldgenParams::from_toml(&TomlAsset::read(&assets.join("worldgen.toml"))?, &blocks)
+            .map_err(|errors| anyhow!("invalid `worldgen.toml`:\n{errors}"))?;
+
+    let db = block_on(Db::open(&save.db_path()))
+        .with_context(|| format!("failed to open world `{}`", save.name()))?;
+    let noise = Noise::load(save, &db);
+    println!("world `{}`, seed {}", save.name(), noise.seed());
+
+    let offsets = area.offsets();
+    let start = Instant::now();
+    let mut generated_count = 0;
+
+    for (i, batch) in offsets.chunks(PREGEN_BATCH).enumerate() {
+        let saved = block_on(db.get_chunk_offsets(batch.iter()));
+        let batch: Vec<_> = batch
+            .iter()
+            .filter(|offset| !saved.contains(*offset))
+            .copied()
+            .collect();
+
+        let (mut generated, mut pending) = generate_chunks(&batch, &noise, &params, &blocks);
+        generated_count += generated.len();
+
+        block_on(async {
+            // Blocks left by features of chunks generated in earlier batches.
+            for (offset, stored) in db.take_pending_blocks(batch.iter()).await {
+                generated.get_mut(&offset).unwrap().place_pending(&stored);
+            }
+
+            db.insert_chunks(
+                generated
+                    .into_iter()
+                    .map(|(offset, chunk)| (offset, chunk.into())),
+            )
+            .await;
+            db.discard_pending_for_edited(&mut pending).await;
+            db.insert_pending_blocks(pending).await;
+        });
+
+        let done = (i * PREGEN_BATCH + PREGEN_BATCH).min(offsets.len());
+        println!(
+            "{done}/{} chunks, {generated_count} generated, {:.0} chunks/s",
+            offsets.len(),
+            generated_count as f64 / start.elapsed().as_secs_f64()
+        );
+    }
+
+    println!(
+        "generated {generated_count} chunks in {:.1}s",
+        start.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_radius() {
+        assert_eq!("16".parse::<ChunkArea>().unwrap(), ChunkArea::Radius(16));
+        assert_eq!("0".parse::<ChunkArea>().unwrap().offsets(), [IVec2::ZERO]);
+        assert!("-1".parse::<ChunkArea>().is_err());
+        assert!("far".parse::<ChunkArea>().is_err());
+    }
+
+    #[test]
+    fn parse_rect() {
+        let area = ChunkArea::Rect(IVec2::new(-8, -2), IVec2::new(8, 2));
+        for s in ["-8,-2:8,2", "8,2:-8,-2", "-8,2:8,-2", " 8, -2:-8 ,2"] {
+            assert_eq!(s.parse::<ChunkArea>().unwrap(), area);
+        }
+        assert_eq!(area.offsets().len(), 17 * 5);
+        assert_eq!(area.offsets()[0], IVec2::ZERO);
+
+        assert!("1,2".parse::<ChunkArea>().is_err());
+        assert!("1,2:3".parse::<ChunkArea>().is_err());
+    }
+}
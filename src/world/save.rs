@@ -0,0 +1,95 @@
+use std::{
+    collections::hash_map::RandomState,
+    fs,
+    hash::BuildHasher,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+
+#[derive(Resource, Clone, Debug)]
+pub(crate) struct Save {
+    name: String,
+    seed: Option<u32>,
+}
+
+impl Save {
+    const SAVES_DIR: &'static str = "saves";
+    const DB_FILE: &'static str = "world.db";
+    const DEFAULT_NAME: &'static str = "world";
+
+    pub(crate) fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            seed: None,
+        }
+    }
+
+    /// Sets the seed used if the world does not exist yet. Numbers are used as is, other strings
+    /// are hashed.
+    pub(crate) fn with_seed(mut self, seed: &str) -> Self {
+        self.seed = Some(parse_seed(seed));
+        self
+    }
+
+    /// Saved worlds, sorted by name.
+    pub(crate) fn list() -> Vec<Self> {
+        let Ok(entries) = fs::read_dir(Self::SAVES_DIR) else {
+            return Vec::new();
+        };
+
+        let mut saves: Vec<_> = entries
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().join(Self::DB_FILE).is_file())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .map(Self::new)
+            .collect();
+        saves.sort_by(|a, b| a.name.cmp(&b.name));
+        saves
+    }
+
+    /// New world named after the first of `world`, `world 2`, `world 3`... that isn't taken.
+    pub(crate) fn unused() -> Self {
+        (1..)
+            .map(|i| match i {
+                1 => Self::new(Self::DEFAULT_NAME),
+                _ => Self::new(format!("{} {i}", Self::DEFAULT_NAME)),
+            })
+            .find(|save| !save.dir().exists())
+            .unwrap()
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Seed for a new world, random unless one was provided.
+    pub(super) fn new_seed(&self) -> u32 {
+        self.seed
+            .unwrap_or_else(|| RandomState::new().hash_one(&self.name) as u32)
+    }
+
+    pub(super) fn requested_seed(&self) -> Option<u32> {
+        self.seed
+    }
+
+    pub(crate) fn dir(&self) -> PathBuf {
+        Path::new(Self::SAVES_DIR).join(&self.name)
+    }
+
+    pub(super) fn db_path(&self) -> PathBuf {
+        self.dir().join(Self::DB_FILE)
+    }
+}
+
+/// Numbers are used as is, other strings are hashed.
+pub(crate) fn parse_seed(seed: &str) -> u32 {
+    seed.parse().unwrap_or_else(|_| fnv1a(seed.as_bytes()))
+}
+
+/// 32-bit FNV-1a, which unlike the standard library hashers is guaranteed to stay the same.
+pub(super) fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c9dc5, |hash, &byte| {
+        (hash ^ byte as u32).wrapping_mul(0x01000193)
+    })
+}
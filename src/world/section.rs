@@ -0,0 +1,297 @@
+use bevy::prelude::*;
+
+use crate::block::BlockId;
+
+use super::{CHUNK_WIDTH, SECTION_HEIGHT, SECTION_VOLUME};
+
+/// Palette-compressed blocks of a 16x16x16 section.
+///
+/// Every block is stored as an index into `palette`, packed into `bits` bits. Sections made of a
+/// single block use zero bits and don't allocate any data.
+///
+/// `counts` holds the number of blocks using every palette entry. Entries no longer used are
+/// reused for new blocks, and the palette is compacted once the used ones fit in fewer bits.
+#[derive(Clone, Debug)]
+pub(super) struct Section {
+    palette: Vec<BlockId>,
+    counts: Vec<u16>,
+    bits: usize,
+    data: Vec<u64>,
+}
+
+impl Section {
+    pub(super) fn from_blocks(blocks: &[BlockId]) -> Self {
+        debug_assert_eq!(blocks.len(), SECTION_VOLUME);
+
+        let mut palette = Vec::new();
+        let mut counts = Vec::new();
+        for &block in blocks {
+            match palette.iter().position(|&b| b == block) {
+                Some(idx) => counts[idx] += 1,
+                None => {
+                    palette.push(block);
+                    counts.push(1);
+                }
+            }
+        }
+
+        let bits = Self::bits_for(palette.len());
+        let mut section = Self {
+            palette,
+            counts,
+            bits,
+            data: vec![0; Self::words_for(bits)],
+        };
+
+        if bits > 0 {
+            for (i, block) in blocks.iter().enumerate() {
+                let idx = section.palette.iter().position(|b| b == block).unwrap();
+                section.write(i, idx);
+            }
+        }
+
+        section
+    }
+
+    pub(super) fn get(&self, pos: IVec3) -> BlockId {
+        self.palette[self.read(Self::index(pos))]
+    }
+
+    pub(super) fn set(&mut self, pos: IVec3, block: BlockId) {
+        let i = Self::index(pos);
+        let old = self.read(i);
+        if self.palette[old] == block {
+            return;
+        }
+        self.counts[old] -= 1;
+
+        let idx = match self.palette.iter().position(|&b| b == block) {
+            Some(idx) => idx,
+            None => match self.counts.iter().position(|&count| count == 0) {
+                Some(idx) => {
+                    self.palette[idx] = block;
+                    idx
+                }
+                None if self.palette.len() < 1 << self.bits => {
+                    self.palette.push(block);
+                    self.counts.push(0);
+                    self.palette.len() - 1
+                }
+                None => return self.rebuild(i, block),
+            },
+        };
+        self.counts[idx] += 1;
+
+        if self.counts[old] == 0 {
+            let used = self.counts.iter().filter(|&&count| count > 0).count();
+            if Self::bits_for(used) < self.bits {
+                return self.rebuild(i, block);
+            }
+        }
+
+        self.write(i, idx);
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.palette
+            .iter()
+            .zip(&self.counts)
+            .all(|(&block, &count)| count == 0 || block == BlockId::AIR)
+    }
+
+    /// Iterates over all blocks in `x`, `z`, `y` order.
+    pub(super) fn iter(&self) -> impl Iterator<Item = BlockId> + '_ {
+        (0..SECTION_VOLUME).map(|i| self.palette[self.read(i)])
+    }
+
+    /// Serializes the section as the palette length minus one, the palette and the packed
+    /// indices as little endian words.
+    pub(super) fn write_bytes(&self, bytes: &mut Vec<u8>) {
+        bytes.push((self.palette.len() - 1) as u8);
+        bytes.extend(self.palette.iter().map(|&block| u8::from(block)));
+        for word in &self.data {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+    }
+
+    pub(super) fn read_bytes(bytes: &mut &[u8]) -> Option<Self> {
+        let (&len, rest) = bytes.split_first()?;
+        let len = len as usize + 1;
+
+        let palette = rest.get(..len)?;
+        let palette: Vec<_> = palette.iter().map(|&block| BlockId::from(block)).collect();
+
+        let bits = Self::bits_for(len);
+        let words = Self::words_for(bits);
+        let data = rest.get(len..len + words * size_of::<u64>())?;
+        let data = data
+            .chunks_exact(size_of::<u64>())
+            .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+            .collect();
+
+        *bytes = &rest[len + words * size_of::<u64>()..];
+
+        let mut section = Self {
+            palette,
+            counts: vec![0; len],
+            bits,
+            data,
+        };
+        for i in 0..SECTION_VOLUME {
+            let idx = section.read(i);
+            *section.counts.get_mut(idx)? += 1;
+        }
+
+        Some(section)
+    }
+
+    /// Recreates the section with `block` at index `i`, keeping only the palette entries in use.
+    fn rebuild(&mut self, i: usize, block: BlockId) {
+        let mut blocks: Vec<_> = self.iter().collect();
+        blocks[i] = block;
+        *self = Self::from_blocks(&blocks);
+    }
+
+    fn index(pos: IVec3) -> usize {
+        debug_assert!(pos.min_element() >= 0 && pos.y < SECTION_HEIGHT as i32);
+        (pos.x + pos.y * (CHUNK_WIDTH * CHUNK_WIDTH) as i32 + pos.z * CHUNK_WIDTH as i32) as usize
+    }
+
+    /// Indices never straddle two words, so only powers of two are used.
+    fn bits_for(palette_len: usize) -> usize {
+        match palette_len {
+            0..=1 => 0,
+            2 => 1,
+            3..=4 => 2,
+            5..=16 => 4,
+            _ => 8,
+        }
+    }
+
+    fn words_for(bits: usize) -> usize {
+        SECTION_VOLUME * bits / u64::BITS as usize
+    }
+
+    fn read(&self, i: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+        let per_word = u64::BITS as usize / self.bits;
+        let shift = (i % per_word) * self.bits;
+        ((self.data[i / per_word] >> shift) & ((1 << self.bits) - 1)) as usize
+    }
+
+    fn write(&mut self, i: usize, idx: usize) {
+        let per_word = u64::BITS as usize / self.bits;
+        let shift = (i % per_word) * self.bits;
+        let mask = ((1 << self.bits) - 1) << shift;
+        let word = &mut self.data[i / per_word];
+        *word = (*word & !mask) | ((idx as u64) << shift);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every position of a section, in the order of the flat layout.
+    fn positions() -> impl Iterator<Item = IVec3> {
+        (0..SECTION_HEIGHT as i32).flat_map(|y| {
+            (0..CHUNK_WIDTH as i32)
+                .flat_map(move |z| (0..CHUNK_WIDTH as i32).map(move |x| IVec3::new(x, y, z)))
+        })
+    }
+
+    /// Checks the section against the flat layout, also after a round trip through bytes.
+    fn assert_matches(section: &Section, flat: &[BlockId]) {
+        assert!(section.iter().eq(flat.iter().copied()));
+        for (pos, &block) in positions().zip(flat) {
+            assert_eq!(section.get(pos), block);
+        }
+        assert_eq!(section.is_empty(), flat.iter().all(|&b| b == BlockId::AIR));
+
+        let mut bytes = Vec::new();
+        section.write_bytes(&mut bytes);
+        let mut rest = bytes.as_slice();
+        let read = Section::read_bytes(&mut rest).unwrap();
+        assert!(rest.is_empty());
+        assert!(read.iter().eq(flat.iter().copied()));
+    }
+
+    #[test]
+    fn uniform_section() {
+        for block in [BlockId::AIR, BlockId::from(3)] {
+            let flat = [block; SECTION_VOLUME];
+            let section = Section::from_blocks(&flat);
+            assert_eq!(section.bits, 0);
+            assert!(section.data.is_empty());
+            assert_matches(&section, &flat);
+        }
+    }
+
+    #[test]
+    fn palette_grows() {
+        let mut flat = [BlockId::AIR; SECTION_VOLUME];
+        let mut section = Section::from_blocks(&flat);
+
+        // Palettes of 2, 3 to 4, 5 to 16 and more entries.
+        for block in 1..=20u8 {
+            let i = block as usize * 37;
+            flat[i] = BlockId::from(block);
+            section.set(positions().nth(i).unwrap(), BlockId::from(block));
+            let bits = match block + 1 {
+                2 => 1,
+                3..=4 => 2,
+                5..=16 => 4,
+                _ => 8,
+            };
+            assert_eq!(section.bits, bits);
+            assert_matches(&section, &flat);
+        }
+    }
+
+    #[test]
+    fn set_then_get_everywhere() {
+        let mut flat = [BlockId::AIR; SECTION_VOLUME];
+        let mut section = Section::from_blocks(&flat);
+
+        for (i, pos) in positions().enumerate() {
+            let block = BlockId::from((i * 7 % 20) as u8);
+            flat[i] = block;
+            section.set(pos, block);
+            assert_eq!(section.get(pos), block);
+        }
+        assert_matches(&section, &flat);
+    }
+
+    #[test]
+    fn palette_is_compacted() {
+        let flat = [BlockId::AIR; SECTION_VOLUME];
+        let mut section = Section::from_blocks(&flat);
+
+        for block in 1..=20 {
+            section.set(
+                IVec3::new(block, 0, 0).min(IVec3::splat(15)),
+                BlockId::from(block as u8),
+            );
+            section.set(IVec3::new(0, 1, block % 16), BlockId::from(block as u8));
+        }
+        for pos in positions() {
+            section.set(pos, BlockId::AIR);
+        }
+        assert_eq!(section.bits, 0);
+        assert_eq!(section.palette, [BlockId::AIR]);
+        assert!(section.data.is_empty());
+        assert_matches(&section, &flat);
+
+        // Unused entries are reused instead of growing the palette.
+        let stone = BlockId::from(3);
+        section.set(IVec3::ZERO, stone);
+        section.set(IVec3::ZERO, BlockId::from(4));
+        assert_eq!(section.palette.len(), 2);
+        assert!(!section.palette.contains(&stone));
+        section.set(IVec3::ONE, BlockId::from(5));
+        assert_eq!(section.bits, 2);
+        assert_eq!(section.palette.len(), 3);
+    }
+}
@@ -7,83 +7,121 @@ use bevy::{
     utils::{HashMap, HashSet},
 };
 use itertools::Itertools;
-use rayon::iter::{IntoParallelRefIterator, ParallelBridge, ParallelIterator};
+use rayon::iter::{IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator};
 
 use crate::{
-    materials::ChunkMaterial, player::PlayerChunkMoveEvent, settings::RENDER_DISTANCE,
-    textures::BlocksTexture,
+    block::BlockRegistry,
+    physics::PhysicalPosition,
+    player::{Player, PlayerChunkMoveEvent},
+    settings::Settings,
 };
 
 use super::{
-    db::Db, mesh::ChunkMeshingTasks, Chunk, ChunkEntities, Chunks, DirtyChunks, Noise, WorldPlugin,
-    WorldgenParams,
+    db::Db,
+    feature::{merge_pending, PendingBlocks},
+    mesh::ChunkMeshingTasks,
+    spawn_point::WorldSpawn,
+    world_to_local, Chunk, ChunkEntities, Chunks, DirtyChunks, Noise, WorldPlugin, WorldgenParams,
+    CHUNK_SECTIONS, CHUNK_WIDTH,
 };
 
 #[derive(Resource, Default, Debug)]
-pub(super) struct ChunkSpawningTasks(HashMap<IVec2, Task<Chunk>>);
+pub(super) struct ChunkSpawningTasks(HashMap<IVec2, Task<(Chunk, PendingBlocks)>>);
+
+/// Chunks of the spawn area that are loaded, and those of them whose sections are all meshed.
+#[derive(Resource, Debug)]
+pub(crate) struct GenerationProgress {
+    area: HashSet<IVec2>,
+    generated: usize,
+    meshed: usize,
+}
+
+impl GenerationProgress {
+    fn new(area: impl IntoIterator<Item = IVec2>) -> Self {
+        Self {
+            area: area.into_iter().collect(),
+            generated: 0,
+            meshed: 0,
+        }
+    }
+
+    pub(crate) fn generated(&self) -> usize {
+        self.generated
+    }
+
+    pub(crate) fn meshed(&self) -> usize {
+        self.meshed
+    }
+
+    pub(crate) fn target(&self) -> usize {
+        self.area.len()
+    }
+
+    /// Fraction of the work done, counting generating and meshing a chunk as separate steps.
+    pub(crate) fn fraction(&self) -> f32 {
+        (self.generated + self.meshed) as f32 / (2 * self.target()).max(1) as f32
+    }
+
+    pub(super) fn is_done(&self) -> bool {
+        self.meshed == self.target()
+    }
+}
 
 impl WorldPlugin {
-    pub(super) fn generate_world(
+    /// Queues the chunks around the spawn, which are generated and meshed over the following
+    /// frames while the loading screen is shown. New worlds are generated around the origin, see
+    /// [`WorldSpawn`].
+    pub(super) fn start_generating(
         mut commands: Commands,
-        noise: Res<Noise>,
-        params: Res<WorldgenParams>,
-        texture: Res<BlocksTexture>,
+        settings: Res<Settings>,
         db: Res<Db>,
-        mut chunks: ResMut<Chunks>,
-        mut entities: ResMut<ChunkEntities>,
-        mut materials: ResMut<Assets<ChunkMaterial>>,
-        mut meshes: ResMut<Assets<Mesh>>,
+        mut events: EventWriter<PlayerChunkMoveEvent>,
     ) {
-        let noise = noise.clone();
-        let params = params.clone();
+        let spawn = block_on(db.spawn()).map(WorldSpawn);
+        let center = spawn.map_or(IVec2::ZERO, |spawn| world_to_local(spawn.0).0);
+        if let Some(spawn) = spawn {
+            commands.insert_resource(spawn);
+        }
 
-        #[cfg(debug_assertions)]
-        let radius = RENDER_DISTANCE;
-        #[cfg(not(debug_assertions))]
-        let radius = RENDER_DISTANCE * 2;
+        commands.insert_resource(GenerationProgress::new(chunks_around(
+            center,
+            settings.render_distance,
+        )));
+        events.send(PlayerChunkMoveEvent::new(center));
+    }
 
-        let world: Vec<_> = chunks_around(IVec2::ZERO, radius)
-            .par_bridge()
-            .map(|offset| (offset, Arc::new(Chunk::generate(offset, &noise, &params))))
-            .collect();
+    pub(super) fn update_generation_progress(
+        chunks: Res<Chunks>,
+        dirty: Res<DirtyChunks>,
+        meshing_tasks: Res<ChunkMeshingTasks>,
+        mut progress: ResMut<GenerationProgress>,
+    ) {
+        let is_meshed = |offset: IVec2| {
+            (0..CHUNK_SECTIONS as i32).all(|section| {
+                let section = IVec3::new(offset.x, section, offset.y);
+                !dirty.0.contains(&section) && !meshing_tasks.0.contains_key(&section)
+            })
+        };
 
-        chunks.0.extend(world.iter().filter_map(|(offset, chunk)| {
-            if distance_between(IVec2::ZERO, *offset) <= RENDER_DISTANCE as f32 {
-                Some((*offset, chunk.clone()))
-            } else {
-                None
-            }
-        }));
-
-        block_on(db.insert_chunks(world));
-
-        entities.0.extend(
-            chunks
-                .0
-                .par_iter()
-                .map(|(&offset, chunk)| (offset, chunk.get_mesh(&chunks.get_neighbors(offset))))
-                .collect::<Vec<_>>()
-                .into_iter()
-                .map(|(offset, mesh)| {
-                    (
-                        offset,
-                        commands
-                            .spawn(MaterialMeshBundle {
-                                material: materials.add(ChunkMaterial::new(offset, &texture.0)),
-                                mesh: meshes.add(mesh),
-                                ..Default::default()
-                            })
-                            .id(),
-                    )
-                }),
-        );
+        let generated: Vec<_> = progress
+            .area
+            .iter()
+            .copied()
+            .filter(|offset| chunks.0.contains_key(offset))
+            .collect();
+        progress.generated = generated.len();
+        progress.meshed = generated
+            .into_iter()
+            .filter(|offset| is_meshed(*offset))
+            .count();
     }
 
     pub(super) fn spawn_chunks(
         mut events: EventReader<PlayerChunkMoveEvent>,
         noise: Res<Noise>,
-        entities: Res<ChunkEntities>,
         params: Res<WorldgenParams>,
+        blocks: Res<BlockRegistry>,
+        settings: Res<Settings>,
         db: Res<Db>,
         mut chunks: ResMut<Chunks>,
         mut dirty: ResMut<DirtyChunks>,
@@ -93,16 +131,18 @@ impl WorldPlugin {
         let noise = Arc::new(noise.clone());
 
         for ev in events.read() {
-            let offsets = chunks_around(ev.new_offset, RENDER_DISTANCE)
-                .filter(|offset| !entities.0.contains_key(offset));
+            let mut offsets: HashSet<_> = chunks_around(ev.new_offset, settings.render_distance)
+                .filter(|offset| !chunks.0.contains_key(offset) && !tasks.0.contains_key(offset))
+                .collect();
 
             let offsets = block_on(async {
-                let mut offsets = HashSet::from_iter(offsets);
-                for row in db.get_chunks(offsets.iter()).await {
-                    let offset = IVec2::new(row.x, row.z);
-                    chunks.0.insert(offset, Arc::new(row.blocks));
-                    dirty.0.insert(offset);
-                    offsets.remove(&IVec2::new(row.x, row.z));
+                for (offset, mut chunk) in load_chunks(&db, offsets.iter()).await {
+                    chunk.compute_light(&blocks);
+                    for pos in chunks.insert(offset, Arc::new(chunk), &blocks) {
+                        dirty.insert_block(pos);
+                    }
+                    dirty.insert(offset);
+                    offsets.remove(&offset);
                 }
                 offsets
             });
@@ -110,8 +150,12 @@ impl WorldPlugin {
             for offset in offsets {
                 let noise = noise.clone();
                 let params = params.clone();
-                let task =
-                    thread_pool.spawn(async move { Chunk::generate(offset, &noise, &params) });
+                let blocks = blocks.clone();
+                let task = thread_pool.spawn(async move {
+                    let (mut chunk, pending) = Chunk::generate(offset, &noise, &params, &blocks);
+                    chunk.compute_light(&blocks);
+                    (chunk, pending)
+                });
                 tasks.0.insert(offset, task);
             }
         }
@@ -119,6 +163,7 @@ impl WorldPlugin {
 
     pub(super) fn despawn_chunks(
         mut chunk_move_events: EventReader<PlayerChunkMoveEvent>,
+        settings: Res<Settings>,
         mut chunks: ResMut<Chunks>,
         mut dirty: ResMut<DirtyChunks>,
         mut spawning_tasks: ResMut<ChunkSpawningTasks>,
@@ -130,7 +175,7 @@ impl WorldPlugin {
 
             for &offset in chunks.0.keys() {
                 let distance = distance_between(player_offset, offset);
-                if distance > RENDER_DISTANCE as f32 {
+                if distance > settings.render_distance as f32 {
                     to_remove.push(offset);
                 }
             }
@@ -138,85 +183,241 @@ impl WorldPlugin {
             for offset in &to_remove {
                 chunks.0.remove(offset);
                 spawning_tasks.0.remove(offset);
-                meshing_tasks.0.remove(offset);
+                meshing_tasks.0.retain(|section, _| section.xz() != *offset);
                 dirty.insert(*offset);
             }
         }
     }
 
+    /// Discards the chunks that weren't edited by the player after `WorldgenParams` were reloaded,
+    /// and generates them again around the player.
+    pub(super) fn regenerate_chunks(
+        params: Res<WorldgenParams>,
+        db: Res<Db>,
+        blocks: Res<BlockRegistry>,
+        query: Query<&PhysicalPosition, With<Player>>,
+        mut chunks: ResMut<Chunks>,
+        mut dirty: ResMut<DirtyChunks>,
+        mut spawning_tasks: ResMut<ChunkSpawningTasks>,
+        mut meshing_tasks: ResMut<ChunkMeshingTasks>,
+        mut events: EventWriter<PlayerChunkMoveEvent>,
+    ) {
+        if params.is_added() {
+            return;
+        }
+        let Ok(player) = query.get_single() else {
+            return;
+        };
+
+        let edited = block_on(db.delete_generated_chunks());
+        spawning_tasks.0.clear();
+        meshing_tasks
+            .0
+            .retain(|section, _| edited.contains(&section.xz()));
+
+        // Light of the edited chunks may come from chunks that are about to change.
+        let mut kept: Vec<_> = chunks
+            .0
+            .drain()
+            .filter(|(offset, _)| edited.contains(offset))
+            .collect();
+        kept.par_iter_mut().for_each(|(_, chunk)| {
+            Arc::make_mut(chunk).compute_light(&blocks);
+        });
+        for (offset, chunk) in kept {
+            for pos in chunks.insert(offset, chunk, &blocks) {
+                dirty.insert_block(pos);
+            }
+            dirty.insert(offset);
+        }
+
+        events.send(PlayerChunkMoveEvent::new(player_offset(player)));
+    }
+
+    /// Loads or unloads chunks around the player when the render distance changes.
+    pub(super) fn update_render_distance(
+        settings: Res<Settings>,
+        query: Query<&PhysicalPosition, With<Player>>,
+        mut events: EventWriter<PlayerChunkMoveEvent>,
+        mut render_distance: Local<Option<i32>>,
+    ) {
+        let previous = render_distance.replace(settings.render_distance);
+        if previous.is_none() || previous == Some(settings.render_distance) {
+            return;
+        }
+        let Ok(player) = query.get_single() else {
+            return;
+        };
+
+        events.send(PlayerChunkMoveEvent::new(player_offset(player)));
+    }
+
     pub(super) fn sync_dirty_chunks(chunks: Res<Chunks>, mut dirty: ResMut<DirtyChunks>) {
         if !chunks.is_changed() {
             return;
         }
-        dirty.0.retain(|offset| chunks.0.contains_key(offset));
+        dirty.0.retain(|offset| chunks.0.contains_key(&offset.xz()));
     }
 
     pub(super) fn sync_chunk_entities(
         mut commands: Commands,
         chunks: Res<Chunks>,
-        texture: Res<BlocksTexture>,
         mut entities: ResMut<ChunkEntities>,
-        mut materials: ResMut<Assets<ChunkMaterial>>,
     ) {
         if !chunks.is_changed() {
             return;
         }
 
-        entities.0.retain(|offset, entity| {
-            if !chunks.0.contains_key(offset) {
+        entities.0.retain(|(offset, _), entity| {
+            if !chunks.0.contains_key(&offset.xz()) {
                 commands.entity(*entity).despawn();
                 false
             } else {
                 true
             }
         });
-
-        for offset in chunks.0.keys() {
-            if entities.0.contains_key(offset) {
-                continue;
-            }
-
-            entities.0.insert(
-                *offset,
-                commands
-                    .spawn(MaterialMeshBundle {
-                        material: materials.add(ChunkMaterial::new(*offset, &texture.0)),
-                        ..Default::default()
-                    })
-                    .id(),
-            );
-        }
     }
 
     pub(super) fn handle_spawning_tasks(
         db: Res<Db>,
+        blocks: Res<BlockRegistry>,
         mut tasks: ResMut<ChunkSpawningTasks>,
         mut chunks: ResMut<Chunks>,
         mut dirty: ResMut<DirtyChunks>,
     ) {
-        let mut spawned_chunks = Vec::new();
+        let mut spawned = Vec::new();
+        let mut pending = PendingBlocks::new();
 
         tasks.0.retain(|&offset, task| {
-            if let Some(chunk) = block_on(future::poll_once(task)) {
-                let chunk = Arc::new(chunk);
-                chunks.0.insert(offset, chunk.clone());
-                dirty.insert(offset);
-                spawned_chunks.push((offset, chunk));
+            if let Some((chunk, chunk_pending)) = block_on(future::poll_once(task)) {
+                spawned.push((offset, chunk));
+                merge_pending(&mut pending, chunk_pending);
                 false
             } else {
                 true
             }
         });
 
-        if spawned_chunks.is_empty() {
+        if spawned.is_empty() {
             return;
         }
 
-        block_on(db.insert_chunks(spawned_chunks));
+        let offsets: Vec<_> = spawned.iter().map(|(offset, _)| *offset).collect();
+        merge_pending(
+            &mut pending,
+            block_on(db.take_pending_blocks(offsets.iter())),
+        );
+
+        let mut spawned_chunks = Vec::new();
+        for (offset, mut chunk) in spawned {
+            if pending
+                .remove(&offset)
+                .is_some_and(|pending| chunk.place_pending(&pending))
+            {
+                chunk.compute_light(&blocks);
+            }
+
+            let chunk = Arc::new(chunk);
+            for pos in chunks.insert(offset, chunk.clone(), &blocks) {
+                dirty.insert_block(pos);
+            }
+            dirty.insert(offset);
+            spawned_chunks.push((offset, chunk));
+        }
+
+        // Blocks of chunks that are already loaded are placed right away, the rest is placed once
+        // they are loaded.
+        block_on(db.discard_pending_for_edited(&mut pending));
+        pending.retain(
+            |&offset, pending| match chunks.place_pending(offset, pending, &blocks) {
+                Some(changed) => {
+                    if !changed.is_empty() {
+                        spawned_chunks.push((offset, chunks.0[&offset].clone()));
+                    }
+                    for pos in changed {
+                        dirty.insert_block(pos);
+                    }
+                    false
+                }
+                None => true,
+            },
+        );
+
+        block_on(async {
+            db.insert_chunks(spawned_chunks).await;
+            db.insert_pending_blocks(pending).await;
+        });
     }
 }
 
-fn chunks_around(origin: IVec2, radius: i32) -> impl Iterator<Item = IVec2> {
+/// Generates chunks in parallel, placing the blocks of features that reach into other generated
+/// chunks. Returns the chunks, and the blocks left for chunks that weren't generated.
+pub(super) fn generate_chunks(
+    offsets: &[IVec2],
+    noise: &Noise,
+    params: &WorldgenParams,
+    blocks: &BlockRegistry,
+) -> (HashMap<IVec2, Chunk>, PendingBlocks) {
+    let generated: Vec<_> = offsets
+        .par_iter()
+        .map(|&offset| (offset, Chunk::generate(offset, noise, params, blocks)))
+        .collect();
+
+    let mut pending = PendingBlocks::new();
+    let mut generated: HashMap<_, _> = generated
+        .into_iter()
+        .map(|(offset, (chunk, chunk_pending))| {
+            merge_pending(&mut pending, chunk_pending);
+            (offset, chunk)
+        })
+        .collect();
+    pending.retain(|offset, pending| match generated.get_mut(offset) {
+        Some(chunk) => {
+            chunk.place_pending(pending);
+            false
+        }
+        None => true,
+    });
+
+    (generated, pending)
+}
+
+/// Loads saved chunks, placing the blocks left for them by features of their neighbors.
+async fn load_chunks<'a, I>(db: &Db, offsets: I) -> Vec<(IVec2, Chunk)>
+where
+    I: IntoIterator<Item = &'a IVec2>,
+{
+    let rows = db.get_chunks(offsets).await;
+    let offsets: Vec<_> = rows.iter().map(|row| IVec2::new(row.x, row.z)).collect();
+    let mut pending = db.take_pending_blocks(offsets.iter()).await;
+
+    let mut loaded = Vec::with_capacity(rows.len());
+    let mut edited = Vec::new();
+    for row in rows {
+        let offset = IVec2::new(row.x, row.z);
+        let mut chunk = row.blocks;
+        if pending
+            .remove(&offset)
+            .is_some_and(|pending| chunk.place_pending(&pending))
+        {
+            edited.push((offset, Arc::new(chunk.clone())));
+        }
+        loaded.push((offset, chunk));
+    }
+
+    db.insert_chunks(edited).await;
+    loaded
+}
+
+fn player_offset(player: &PhysicalPosition) -> IVec2 {
+    player
+        .current()
+        .xz()
+        .as_ivec2()
+        .div_euclid(IVec2::splat(CHUNK_WIDTH as i32))
+}
+
+pub(super) fn chunks_around(origin: IVec2, radius: i32) -> impl Iterator<Item = IVec2> {
     let iter_x = (-radius..=radius).map(move |x| x + origin.x);
     let iter_z = (-radius..=radius).map(move |z| z + origin.y);
 
@@ -0,0 +1,137 @@
+use bevy::{prelude::*, tasks::block_on};
+
+use crate::block::{BlockId, BlockRegistry};
+
+use super::{db::Db, spawn::GenerationProgress, Chunks, WorldPlugin, CHUNK_HEIGHT};
+
+/// Block the player spawns on top of, stored with the world.
+#[derive(Resource, Clone, Copy, Debug)]
+pub(crate) struct WorldSpawn(pub(super) IVec3);
+
+impl WorldSpawn {
+    /// Distance in blocks from the center up to which columns are searched.
+    const SEARCH_RADIUS: i32 = 64;
+
+    /// Position of the feet of the player when spawning.
+    pub(crate) fn position(self) -> Vec3 {
+        self.0.as_vec3() + Vec3::new(0.5, 1.0, 0.5)
+    }
+
+    /// Finds the loaded column closest to `center` whose highest block is ground, with two blocks
+    /// of air above it. Ground is solid and opaque, which rules out fluids like the sea and the
+    /// leaves of trees, and isn't the wood of a tree either.
+    fn find(chunks: &Chunks, blocks: &BlockRegistry, center: IVec2) -> Option<Self> {
+        let wood = blocks.id("wood");
+        let is_ground = |id: BlockId| {
+            let block = &blocks[id];
+            block.is_solid() && block.is_opaque() && !block.is_fluid() && Some(id) != wood
+        };
+
+        spiral(center, Self::SEARCH_RADIUS).find_map(|column| {
+            let top = (0..CHUNK_HEIGHT as i32 - 2)
+                .rev()
+                .map(|y| IVec3::new(column.x, y, column.y))
+                .find(|pos| chunks.block_at(*pos) != Some(BlockId::AIR))?;
+            let is_clear =
+                (1..=2).all(|dy| chunks.block_at(top + IVec3::Y * dy) == Some(BlockId::AIR));
+            (is_ground(chunks.block_at(top)?) && is_clear).then_some(Self(top))
+        })
+    }
+}
+
+impl WorldPlugin {
+    /// Searches the generated spawn area for a safe spawn for a world that doesn't have one yet,
+    /// and stores it with the world.
+    pub(super) fn find_world_spawn(
+        mut commands: Commands,
+        progress: Res<GenerationProgress>,
+        chunks: Res<Chunks>,
+        blocks: Res<BlockRegistry>,
+        db: Res<Db>,
+    ) {
+        if !progress.is_done() {
+            return;
+        }
+
+        let spawn = WorldSpawn::find(&chunks, &blocks, IVec2::ZERO).unwrap_or_else(|| {
+            warn!("no safe spawn found, spawning above the origin");
+            WorldSpawn(IVec3::new(0, CHUNK_HEIGHT as i32, 0))
+        });
+        block_on(db.set_spawn(spawn.0));
+        commands.insert_resource(spawn);
+    }
+}
+
+/// Columns in square rings of growing size around `center`, starting with `center` itself.
+fn spiral(center: IVec2, radius: i32) -> impl Iterator<Item = IVec2> {
+    (0..=radius).flat_map(move |ring| {
+        (-ring..=ring).flat_map(move |x| {
+            // Only the outermost rows of a ring are full, the others contribute their two ends.
+            let step = if x.abs() == ring { 1 } else { 2 * ring };
+            (-ring..=ring)
+                .step_by(step as usize)
+                .map(move |z| center + IVec2::new(x, z))
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::world::{Chunk, CHUNK_VOLUME};
+
+    /// Single chunk of air with the given blocks placed in it.
+    fn chunks(placed: &[(IVec3, &str)], blocks: &BlockRegistry) -> Chunks {
+        let mut chunks = Chunks::default();
+        let chunk = Chunk::from_blocks(&[BlockId::AIR; CHUNK_VOLUME]);
+        chunks.0.insert(IVec2::ZERO, Arc::new(chunk));
+        for &(pos, name) in placed {
+            chunks.set_block_at(pos, blocks.id(name).unwrap());
+        }
+        chunks
+    }
+
+    #[test]
+    fn spawns_on_ground() {
+        let blocks = BlockRegistry::from_assets();
+        let ground = IVec3::new(5, 60, 5);
+        let chunks = chunks(&[(ground, "dirt")], &blocks);
+
+        let spawn = WorldSpawn::find(&chunks, &blocks, IVec2::new(3, 3)).unwrap();
+        assert_eq!(spawn.0, ground);
+    }
+
+    #[test]
+    fn skips_features_and_fluids() {
+        let blocks = BlockRegistry::from_assets();
+        let chunks = chunks(
+            &[
+                (IVec3::new(0, 70, 0), "leaves"),
+                (IVec3::new(1, 70, 0), "wood"),
+                (IVec3::new(2, 70, 0), "water"),
+                (IVec3::new(3, 70, 0), "glass"),
+                (IVec3::new(8, 60, 8), "stone"),
+            ],
+            &blocks,
+        );
+
+        let spawn = WorldSpawn::find(&chunks, &blocks, IVec2::ZERO).unwrap();
+        assert_eq!(spawn.0, IVec3::new(8, 60, 8));
+    }
+
+    #[test]
+    fn skips_covered_ground() {
+        let blocks = BlockRegistry::from_assets();
+        let chunks = chunks(
+            &[
+                (IVec3::new(0, 60, 0), "stone"),
+                (IVec3::new(0, 63, 0), "glass"),
+            ],
+            &blocks,
+        );
+
+        assert!(WorldSpawn::find(&chunks, &blocks, IVec2::ZERO).is_none());
+    }
+}